@@ -1,13 +1,14 @@
 use crate::handler::{Handler, RawHandler};
-use abci::{RequestCheckTx, ResponseCheckTx, RequestBeginBlock, RequestDeliverTx, ResponseDeliverTx, ResponseBeginBlock, RequestEndBlock, ResponseEndBlock, ResponseCommit, RequestCommit, RequestInfo, ResponseInfo, RequestInitChain, ResponseInitChain, RequestQuery, ResponseQuery};
+use abci::{Event, KVPair, RequestCheckTx, ResponseCheckTx, RequestBeginBlock, RequestDeliverTx, ResponseDeliverTx, ResponseBeginBlock, RequestEndBlock, ResponseEndBlock, ResponseCommit, RequestCommit, RequestInfo, ResponseInfo, RequestInitChain, ResponseInitChain, RequestQuery, ResponseQuery};
 use crate::tx::Tx;
 use std::error::Error;
 use crate::context::{ABCIPhase, ABCI_PHASE, BLOCK_HEADER};
 use crate::context::ABCIPhase::{BeginBlock, Check, InitChain, Query, Deliver, EndBlock, Commit, Info};
 use crate::config::Config;
 use regen_context::SimpleContext;
+use regen_context::event::{self, collect_events};
 
-struct ABCIBaseApp {
+pub struct ABCIBaseApp {
     base_context: SimpleContext,
     block_context: SimpleContext,
     handler: RawHandler,
@@ -41,32 +42,35 @@ impl abci::Application for ABCIBaseApp {
     }
 
     fn begin_block(&mut self, req: &RequestBeginBlock) -> ResponseBeginBlock {
-        let ctx = &self.base_context
+        let ctx = self.base_context
             .with(&ABCI_PHASE, BeginBlock)
             .with(&BLOCK_HEADER, req.get_header().clone());
         self.block_context = ctx.clone();
-        self.handler.begin_block(&ctx, req)
+        let (mut res, events) = collect_events(&ctx, |ctx| self.handler.begin_block(ctx, req), |_| true);
+        push_events(res.mut_events(), &events);
+        res
     }
 
     fn check_tx(&mut self, req: &RequestCheckTx) -> ResponseCheckTx {
-        self.handler.check(
-            &self.block_context.with(&ABCI_PHASE, Check),
-            &Box::from(req.get_tx()),
-        )
+        let ctx = self.block_context.with(&ABCI_PHASE, Check);
+        let (mut res, events) = collect_events(&ctx, |ctx| self.handler.check(ctx, &Box::from(req.get_tx())), |res| res.get_code() == 0);
+        push_events(res.mut_events(), &events);
+        res
     }
 
     fn deliver_tx(&mut self, req: &RequestDeliverTx) -> ResponseDeliverTx {
-        self.handler.deliver(
-            &self.block_context.with(&ABCI_PHASE, Deliver),
-            &Box::from(req.get_tx()),
-        )
+        let ctx = self.block_context.with(&ABCI_PHASE, Deliver);
+        // the writes of failed transactions are discarded, so are their events and table changes
+        let (mut res, events) = collect_events(&ctx, |ctx| self.handler.deliver(ctx, &Box::from(req.get_tx())), |res| res.get_code() == 0);
+        push_events(res.mut_events(), &events);
+        res
     }
 
     fn end_block(&mut self, req: &RequestEndBlock) -> ResponseEndBlock {
-        self.handler.end_block(
-            &self.block_context.with(&ABCI_PHASE, EndBlock),
-            req
-        )
+        let ctx = self.block_context.with(&ABCI_PHASE, EndBlock);
+        let (mut res, events) = collect_events(&ctx, |ctx| self.handler.end_block(ctx, req), |_| true);
+        push_events(res.mut_events(), &events);
+        res
     }
 
     fn commit(&mut self, req: &RequestCommit) -> ResponseCommit {
@@ -88,4 +92,27 @@ impl ABCIBaseApp {
     fn load_tx(&self, tx_bytes: &[u8]) -> Result<Box<dyn Tx>, Box<dyn Error>> {
         unimplemented!()
     }
-}
\ No newline at end of file
+}
+
+fn push_events(res: &mut protobuf::RepeatedField<Event>, events: &[event::Event]) {
+    for event in events.iter() {
+        res.push(abci_event(event));
+    }
+}
+
+// see `Attribute::index` for why it's dropped
+fn abci_event(event: &event::Event) -> Event {
+    let mut res = Event::new();
+    res.set_field_type(event.event_type.clone());
+    for (key, value) in event.kv_pairs() {
+        res.mut_attributes().push(kv_pair(key, value));
+    }
+    res
+}
+
+fn kv_pair(key: Vec<u8>, value: Vec<u8>) -> KVPair {
+    let mut pair = KVPair::new();
+    pair.set_key(key);
+    pair.set_value(value);
+    pair
+}
@@ -1,12 +1,12 @@
 use clap::{App, ArgMatches};
 use crate::result::Res;
-use regen_context::SimpleContext;
+use regen_context::Context;
 
 pub trait CliHandler<R> {
-    fn build_cli_app(&self, ctx: &SimpleContext, app: App) -> App;
-    fn run_cli_app(&self, ctx: &SimpleContext, matches: ArgMatches) -> Res<&R>;
+    fn build_cli_app(&self, ctx: &dyn Context, app: App) -> App;
+    fn run_cli_app(&self, ctx: &dyn Context, matches: ArgMatches) -> Res<&R>;
 }
 pub trait CliMiddleware<R> {
-    fn on_build_cli_app(&self, ctx: &SimpleContext, app: App, next: &dyn CliHandler<R>) -> App;
-    fn on_run_cli_app(&self, ctx: &SimpleContext, matches: ArgMatches, next: &dyn CliHandler<R>) -> Res<&R>;
+    fn on_build_cli_app(&self, ctx: &dyn Context, app: App, next: &dyn CliHandler<R>) -> App;
+    fn on_run_cli_app(&self, ctx: &dyn Context, matches: ArgMatches, next: &dyn CliHandler<R>) -> Res<&R>;
 }
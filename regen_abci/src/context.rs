@@ -9,12 +9,11 @@ use regen_client_sdk::auth::{Condition, Address};
 use std::any::Any;
 use im::*;
 use im::hashmap::*;
-use std::marker::PhantomData;
 use std::sync::Arc;
 use crate::result::Res;
-use regen_context::{ContextKey, SimpleContext};
+use regen_context::{context_key, Context};
 
-#[derive(Clone)]
+#[derive(Clone, Debug)]
 pub enum ABCIPhase {
     Query,
     Info,
@@ -33,13 +32,13 @@ impl Default for ABCIPhase {
 }
 
 
-pub static mut VERSION: ContextKey<String> = ContextKey("version", PhantomData);
+context_key!(pub VERSION: String, debug);
 
-pub const ABCI_PHASE: ContextKey<ABCIPhase> = ContextKey("abci_phase", PhantomData);
+context_key!(pub ABCI_PHASE: ABCIPhase, debug);
 
-pub const CONFIG: ContextKey<Config> = ContextKey("config", PhantomData);
+context_key!(pub CONFIG: Config);
 
-pub const BLOCK_HEADER: ContextKey<Header> = ContextKey("config", PhantomData);
+context_key!(pub BLOCK_HEADER: Header, debug);
 
 //pub trait Context {
 //    fn readonly_kv_store(&self, key: StoreKey) -> Result<Box<dyn ReadonlyKVStore>, Box<dyn Error>>;
@@ -70,13 +69,13 @@ pub const BLOCK_HEADER: ContextKey<Header> = ContextKey("config", PhantomData);
 //        &self.header
 //    }
 //
-pub fn address_string(ctx: &SimpleContext, addr: &Address) -> Res<String> {
+pub fn address_string(ctx: &dyn Context, addr: &Address) -> Res<String> {
     let cfg = ctx.get(&CONFIG)?;
     let x = bech32::encode(&cfg.address_prefix, addr.0.to_base32())?;
     Ok(x)
 }
 
-pub fn parse_address(ctx: &SimpleContext, str: &String) -> Res<Address> {
+pub fn parse_address(ctx: &dyn Context, str: &String) -> Res<Address> {
     let cfg = ctx.get(&CONFIG)?;
     let (hrp, data) = bech32::decode(str)?;
     if !(hrp.eq(&cfg.address_prefix)) {
@@ -87,7 +86,7 @@ pub fn parse_address(ctx: &SimpleContext, str: &String) -> Res<Address> {
     }
 }
 
-pub fn condition_address(ctx: &SimpleContext, cond: &Condition) -> Address {
+pub fn condition_address(ctx: &dyn Context, cond: &Condition) -> Address {
     let mut hasher = Blake2b::new();
     hasher.input(cond.to_string());
     Address(Box::from(hasher.result().as_slice()))
@@ -3,42 +3,42 @@ use crate::tx::Tx;
 use crate::result::Res;
 use abci;
 use std::ops::{Deref, Shr};
-use regen_context::SimpleContext;
+use regen_context::Context;
 
 //pub type EndBlocker = fn(ctx: &dyn Context, req: &RequestEndBlock) -> ResponseEndBlock;
 //
 //pub type EndBlocker = fn(ctx: &dyn Context, req: &RequestEndBlock) -> ResponseEndBlock;
 
 pub trait Handler<T = Box<dyn Tx>, Q = abci::RequestQuery, CheckRes: Default = CheckResult, DeliverRes: Default = DeliverResult, QueryRes: Default = abci::ResponseQuery> {
-    fn info(&self, ctx: &SimpleContext, req: &abci::RequestInfo) -> abci::ResponseInfo {
+    fn info(&self, ctx: &dyn Context, req: &abci::RequestInfo) -> abci::ResponseInfo {
         abci::ResponseInfo::new()
     }
 
-    fn init_chain(&self, ctx: &SimpleContext, req: &abci::RequestInitChain) -> abci::ResponseInitChain {
+    fn init_chain(&self, ctx: &dyn Context, req: &abci::RequestInitChain) -> abci::ResponseInitChain {
         abci::ResponseInitChain::new()
     }
 
-    fn begin_block(&self, ctx: &SimpleContext, req: &abci::RequestBeginBlock) -> abci::ResponseBeginBlock {
+    fn begin_block(&self, ctx: &dyn Context, req: &abci::RequestBeginBlock) -> abci::ResponseBeginBlock {
         abci::ResponseBeginBlock::new()
     }
 
-    fn check(&self, ctx: &SimpleContext, tx: &T) -> CheckRes {
+    fn check(&self, ctx: &dyn Context, tx: &T) -> CheckRes {
         CheckRes::default()
     }
 
-    fn deliver(&self, ctx: &SimpleContext, tx: &T) -> DeliverRes {
+    fn deliver(&self, ctx: &dyn Context, tx: &T) -> DeliverRes {
         DeliverRes::default()
     }
 
-    fn end_block(&self, ctx: &SimpleContext, req: &abci::RequestEndBlock) -> abci::ResponseEndBlock {
+    fn end_block(&self, ctx: &dyn Context, req: &abci::RequestEndBlock) -> abci::ResponseEndBlock {
         abci::ResponseEndBlock::new()
     }
 
-    fn commit(&mut self, ctx: &SimpleContext, req: &abci::RequestCommit) -> abci::ResponseCommit {
+    fn commit(&mut self, ctx: &dyn Context, req: &abci::RequestCommit) -> abci::ResponseCommit {
         abci::ResponseCommit::new()
     }
 
-    fn query(&self, ctx: &SimpleContext, query: &Q) -> QueryRes {
+    fn query(&self, ctx: &dyn Context, query: &Q) -> QueryRes {
         QueryRes::default()
     }
 }
@@ -53,34 +53,34 @@ pub trait Decorator<
     DeliverRes: Default = DeliverResult,
     QueryRes: Default = abci::ResponseQuery,
 > {
-    fn on_info(&self, ctx: &SimpleContext, req: &abci::RequestInfo, next: &dyn Handler<T, Q, CheckRes, DeliverRes, QueryRes>) -> abci::ResponseInfo {
+    fn on_info(&self, ctx: &dyn Context, req: &abci::RequestInfo, next: &dyn Handler<T, Q, CheckRes, DeliverRes, QueryRes>) -> abci::ResponseInfo {
         next.info(ctx, req)
     }
 
-    fn on_init_chain(&self, ctx: &SimpleContext, req: &abci::RequestInitChain, next: &dyn Handler<T, Q, CheckRes, DeliverRes, QueryRes>) -> abci::ResponseInitChain {
+    fn on_init_chain(&self, ctx: &dyn Context, req: &abci::RequestInitChain, next: &dyn Handler<T, Q, CheckRes, DeliverRes, QueryRes>) -> abci::ResponseInitChain {
         next.init_chain(ctx, req)
     }
 
-    fn on_begin_block(&self, ctx: &SimpleContext, req: &abci::RequestBeginBlock, next: &dyn Handler<T, Q, CheckRes, DeliverRes, QueryRes>) -> abci::ResponseBeginBlock {
+    fn on_begin_block(&self, ctx: &dyn Context, req: &abci::RequestBeginBlock, next: &dyn Handler<T, Q, CheckRes, DeliverRes, QueryRes>) -> abci::ResponseBeginBlock {
         next.begin_block(ctx, req)
     }
 
-    fn on_check(&self, ctx: &SimpleContext, tx: &T, next: &dyn Handler<T, Q, CheckRes, DeliverRes, QueryRes>) -> CheckRes {
+    fn on_check(&self, ctx: &dyn Context, tx: &T, next: &dyn Handler<T, Q, CheckRes, DeliverRes, QueryRes>) -> CheckRes {
         next.check(ctx, tx)
     }
-    fn on_deliver(&self, ctx: &SimpleContext, tx: &T, next: &dyn Handler<T, Q, CheckRes, DeliverRes, QueryRes>) -> DeliverRes {
+    fn on_deliver(&self, ctx: &dyn Context, tx: &T, next: &dyn Handler<T, Q, CheckRes, DeliverRes, QueryRes>) -> DeliverRes {
         next.deliver(ctx, tx)
     }
 
-    fn on_end_block(&self, ctx: &SimpleContext, req: &abci::RequestEndBlock, next: &dyn Handler<T, Q, CheckRes, DeliverRes, QueryRes>) -> abci::ResponseEndBlock {
+    fn on_end_block(&self, ctx: &dyn Context, req: &abci::RequestEndBlock, next: &dyn Handler<T, Q, CheckRes, DeliverRes, QueryRes>) -> abci::ResponseEndBlock {
         next.end_block(ctx, req)
     }
 
-    fn on_commit(&mut self, ctx: &SimpleContext, req: &abci::RequestCommit, next: &mut dyn Handler<T, Q, CheckRes, DeliverRes, QueryRes>) -> abci::ResponseCommit {
+    fn on_commit(&mut self, ctx: &dyn Context, req: &abci::RequestCommit, next: &mut dyn Handler<T, Q, CheckRes, DeliverRes, QueryRes>) -> abci::ResponseCommit {
         next.commit(ctx, req)
     }
 
-    fn on_query(&self, ctx: &SimpleContext, req: &Q, next: &dyn Handler<T, Q, CheckRes, DeliverRes, QueryRes>) -> QueryRes {
+    fn on_query(&self, ctx: &dyn Context, req: &Q, next: &dyn Handler<T, Q, CheckRes, DeliverRes, QueryRes>) -> QueryRes {
         next.query(ctx, req)
     }
 }
@@ -88,35 +88,35 @@ pub trait Decorator<
 struct Chain<T, Q, CheckRes, DeliverRes, QueryRes>(Box<dyn Decorator<T, Q, CheckRes, DeliverRes, QueryRes>>, Box<dyn Handler<T, Q, CheckRes, DeliverRes, QueryRes>>);
 
 impl <T, Q, CheckRes: Default, DeliverRes: Default, QueryRes: Default> Handler<T, Q, CheckRes, DeliverRes, QueryRes> for Chain<T, Q, CheckRes, DeliverRes, QueryRes> {
-    fn info(&self, ctx: &SimpleContext, req: &abci::RequestInfo) -> abci::ResponseInfo {
+    fn info(&self, ctx: &dyn Context, req: &abci::RequestInfo) -> abci::ResponseInfo {
         self.0.on_info(ctx, req, self.1.as_ref())
     }
 
-    fn init_chain(&self, ctx: &SimpleContext, req: &abci::RequestInitChain) -> abci::ResponseInitChain {
+    fn init_chain(&self, ctx: &dyn Context, req: &abci::RequestInitChain) -> abci::ResponseInitChain {
         self.0.on_init_chain(ctx, req, self.1.as_ref())
     }
 
-    fn begin_block(&self, ctx: &SimpleContext, req: &abci::RequestBeginBlock) -> abci::ResponseBeginBlock {
+    fn begin_block(&self, ctx: &dyn Context, req: &abci::RequestBeginBlock) -> abci::ResponseBeginBlock {
         self.0.on_begin_block(ctx, req, self.1.as_ref())
     }
 
-    fn check(&self, ctx: &SimpleContext, tx: &T) -> CheckRes {
+    fn check(&self, ctx: &dyn Context, tx: &T) -> CheckRes {
         self.0.on_check(ctx, tx, self.1.as_ref())
     }
 
-    fn deliver(&self, ctx: &SimpleContext, tx: &T) -> DeliverRes {
+    fn deliver(&self, ctx: &dyn Context, tx: &T) -> DeliverRes {
         self.0.on_deliver(ctx, tx, self.1.as_ref())
     }
 
-    fn end_block(&self, ctx: &SimpleContext, req: &abci::RequestEndBlock) -> abci::ResponseEndBlock {
+    fn end_block(&self, ctx: &dyn Context, req: &abci::RequestEndBlock) -> abci::ResponseEndBlock {
         self.0.on_end_block(ctx, req, self.1.as_ref())
     }
 
-    fn commit(&mut self, ctx: &SimpleContext, req: &abci::RequestCommit) -> abci::ResponseCommit {
+    fn commit(&mut self, ctx: &dyn Context, req: &abci::RequestCommit) -> abci::ResponseCommit {
         self.0.on_commit(ctx, req, self.1.as_ref())
     }
 
-    fn query(&self, ctx: &SimpleContext, query: &Q) -> QueryRes {
+    fn query(&self, ctx: &dyn Context, query: &Q) -> QueryRes {
         self.0.on_query(ctx, query, self.1.as_ref())
     }
 }
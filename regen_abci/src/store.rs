@@ -1,57 +1,88 @@
-use regen_store::{MutableOrderedMap, CommitKVStore, Batch, ReadonlyKVStore};
+use regen_store::{OrderedMap, Batch};
+use regen_store::multi::MultiStore;
 use crate::handler::{Decorator, Handler};
-use regen_context::{SimpleContext, ContextKey};
+use regen_context::{context_key, Context};
+use regen_table::{Marshaller, TableError};
 use abci;
 use crate::result::Res;
 use std::marker::PhantomData;
 use std::rc::Weak;
 
-struct StoreMiddleware<'a> {
-    app_store: Box<dyn CommitKVStore<'a, Vec<u8>, Vec<u8>>>,
-    block_store: Box<dyn Batch<'a, Vec<u8>, Vec<u8>>>
+struct StoreMiddleware {
+    app_store: MultiStore,
+    block_store: Box<dyn Batch<Vec<u8>, Vec<u8>>>
 }
 
-struct ReadonlyKVStoreAccessor(Weak<dyn ReadonlyKVStore<Vec<u8>, Vec<u8>>>);
+struct ReadonlyKVStoreAccessor(Weak<dyn OrderedMap<Vec<u8>, Vec<u8>>>);
 
 pub struct StoreKey(Vec<u8>);
 
+/// Stores protobuf messages as table rows.
+pub struct ProtobufMarshaller<T>(PhantomData<T>);
+
+impl<T> ProtobufMarshaller<T> {
+    pub fn new() -> Self {
+        ProtobufMarshaller(PhantomData)
+    }
+}
+
+impl<T: protobuf::Message> Marshaller<T> for ProtobufMarshaller<T> {
+    fn marshal(&self, value: &T) -> Result<Vec<u8>, TableError> {
+        value.write_to_bytes().map_err(|e| TableError::Wrap(Box::from(e)))
+    }
+
+    fn unmarshal(&self, bz: &[u8]) -> Result<T, TableError> {
+        protobuf::parse_from_bytes(bz).map_err(|e| TableError::Wrap(Box::from(e)))
+    }
+}
+
 impl ReadonlyKVStoreAccessor {
-    fn readonly_kv_store(&self, key: StoreKey) -> Res<&dyn ReadonlyKVStore> {
+    fn readonly_kv_store(&self, key: StoreKey) -> Res<&dyn OrderedMap<Vec<u8>, Vec<u8>>> {
         unimplemented!()
     }
 }
 
-const READONLY_KV_STORE_ACCESSOR: ContextKey<ReadonlyKVStoreAccessor> = ContextKey("readonly_kv_store_accessor", PhantomData);
+context_key!(READONLY_KV_STORE_ACCESSOR: ReadonlyKVStoreAccessor);
 
-impl <'a, T, Q, CheckRes, DeliverRes, QueryRes> Decorator<T, Q, CheckRes, DeliverRes, QueryRes> for StoreMiddleware<'a> {
-    fn on_info(&self, ctx: &SimpleContext, req: &abci::RequestInfo, next: &dyn Handler<T, Q, CheckRes, DeliverRes, QueryRes>) -> abci::ResponseInfo {
-        next.info(&ctx, req)
+impl <T, Q, CheckRes, DeliverRes, QueryRes> Decorator<T, Q, CheckRes, DeliverRes, QueryRes> for StoreMiddleware {
+    fn on_info(&self, ctx: &dyn Context, req: &abci::RequestInfo, next: &dyn Handler<T, Q, CheckRes, DeliverRes, QueryRes>) -> abci::ResponseInfo {
+        next.info(ctx, req)
     }
 
-    fn on_init_chain(&self, ctx: &SimpleContext, req: &abci::RequestInitChain, next: &dyn Handler<T, Q, CheckRes, DeliverRes, QueryRes>) -> ResponseInitChain {
+    fn on_init_chain(&self, ctx: &dyn Context, req: &abci::RequestInitChain, next: &dyn Handler<T, Q, CheckRes, DeliverRes, QueryRes>) -> ResponseInitChain {
         next.init_chain(ctx, req)
     }
 
-    fn on_begin_block(&self, ctx: &SimpleContext, req: &abci::RequestBeginBlock, next: &dyn Handler<T, Q, CheckRes, DeliverRes, QueryRes>) -> ResponseBeginBlock {
+    fn on_begin_block(&self, ctx: &dyn Context, req: &abci::RequestBeginBlock, next: &dyn Handler<T, Q, CheckRes, DeliverRes, QueryRes>) -> ResponseBeginBlock {
         next.begin_block(ctx, req)
     }
 
-    fn on_check(&self, ctx: &SimpleContext, tx: &T, next: &dyn Handler<T, Q, CheckRes, DeliverRes, QueryRes>) -> CheckRes {
+    fn on_check(&self, ctx: &dyn Context, tx: &T, next: &dyn Handler<T, Q, CheckRes, DeliverRes, QueryRes>) -> CheckRes {
         next.check(ctx, tx)
     }
-    fn on_deliver(&self, ctx: &SimpleContext, tx: &T, next: &dyn Handler<T, Q, CheckRes, DeliverRes, QueryRes>) -> DeliverRes {
+    fn on_deliver(&self, ctx: &dyn Context, tx: &T, next: &dyn Handler<T, Q, CheckRes, DeliverRes, QueryRes>) -> DeliverRes {
         next.deliver(ctx, tx)
     }
 
-    fn on_end_block(&self, ctx: &SimpleContext, req: &abci::RequestEndBlock, next: &dyn Handler<T, Q, CheckRes, DeliverRes, QueryRes>) -> ResponseEndBlock {
+    fn on_end_block(&self, ctx: &dyn Context, req: &abci::RequestEndBlock, next: &dyn Handler<T, Q, CheckRes, DeliverRes, QueryRes>) -> ResponseEndBlock {
         next.end_block(ctx, req)
     }
 
-    fn on_commit(&mut self, ctx: &SimpleContext, req: &abci::RequestCommit, next: &mut dyn Handler<T, Q, CheckRes, DeliverRes, QueryRes>) -> ResponseCommit {
-        next.commit(ctx, req)
+    fn on_commit(&mut self, ctx: &dyn Context, req: &abci::RequestCommit, next: &mut dyn Handler<T, Q, CheckRes, DeliverRes, QueryRes>) -> ResponseCommit {
+        let mut res = next.commit(ctx, req);
+        match self.app_store.commit() {
+            Ok(commit) => res.set_data(commit.hash),
+            Err(e) => {
+                // the stores may be partly committed, so this block can't be answered and the
+                // node has to stop, Tendermint replays the block once it is restarted. The error
+                // names the store which failed.
+                panic!("commit of version {} failed: {}", self.app_store.last_commit().version + 1, e)
+            }
+        }
+        res
     }
 
-    fn on_query(&self, ctx: &SimpleContext, req: &Q, next: &dyn Handler<T, Q, CheckRes, DeliverRes, QueryRes>) -> QueryRes {
+    fn on_query(&self, ctx: &dyn Context, req: &Q, next: &dyn Handler<T, Q, CheckRes, DeliverRes, QueryRes>) -> QueryRes {
         next.query(ctx, req)
     }
 }
\ No newline at end of file
@@ -4,7 +4,7 @@ use std::error::Error;
 use crate::tx::{TxBuilder, StdSignature};
 use crate::result::Res;
 use regen_client_sdk::auth::PubKey;
-use regen_context::SimpleContext;
+use regen_context::Context;
 use crate::error::ABCIError;
 
 pub trait KeyBase {
@@ -23,7 +23,7 @@ struct SigCli {
 const FROM: &'static str = "from";
 
 impl CliMiddleware<dyn TxBuilder> for SigCli {
-    fn on_build_cli_app(&self, ctx: &SimpleContext, app: App, next: &dyn CliHandler<&dyn TxBuilder>) -> App {
+    fn on_build_cli_app(&self, ctx: &dyn Context, app: App, next: &dyn CliHandler<&dyn TxBuilder>) -> App {
         next.build_cli_app(
             ctx,
             app.arg(Arg::with_name(FROM)
@@ -35,7 +35,7 @@ impl CliMiddleware<dyn TxBuilder> for SigCli {
         )
     }
 
-    fn on_run_cli_app(&self, ctx: &SimpleContext, matches: ArgMatches, next: &dyn CliHandler<&dyn TxBuilder>) -> Res<&dyn TxBuilder> {
+    fn on_run_cli_app(&self, ctx: &dyn Context, matches: ArgMatches, next: &dyn CliHandler<&dyn TxBuilder>) -> Res<&dyn TxBuilder> {
         let mut bldr = next.run_cli_app(ctx, matches)?;
         let keys = matches.values_of(FROM).ok_or(ABCIError::NotFound)?;
         for key in keys.iter() {
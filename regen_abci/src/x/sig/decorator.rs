@@ -5,16 +5,22 @@ use crate::x::sig::codec;
 use crate::handler::{Decorator, Handler, TxHandler};
 use regen_client_sdk::auth::{Address, Condition, PubKey};
 use crate::x::sig::codec::PubKey_oneof_sum::ed25519;
-use regen_table::Table;
-use regen_context::SimpleContext;
+use regen_table::{Table, TableImpl, StoreKey, store_context};
+use regen_context::Context;
 use crate::context::{BLOCK_HEADER, condition_address};
+use crate::store::ProtobufMarshaller;
 
 pub struct Keeper {
-    auth_table: Box<dyn Table<Address, Account>>
+    auth_table: Box<dyn Table<Vec<u8>, Account>>
 }
 
 pub fn new_keeper() -> Box<Keeper> {
-    Box::from(Keeper { auth_table: unimplemented!() })
+    let auth_table = TableImpl::new(
+        StoreKey::new("auth"),
+        Box::new(ProtobufMarshaller::new()),
+        Box::new(|acc: &Account| acc.address.clone()),
+    );
+    Box::from(Keeper { auth_table: Box::new(auth_table) })
 }
 
 impl Decorator for Keeper {
@@ -34,7 +40,7 @@ impl Decorator for Keeper {
 }
 
 impl Keeper {
-    fn verify_tx_signatures(&self, ctx: &SimpleContext, tx: &dyn Tx) -> Res<Box<[Condition]>> {
+    fn verify_tx_signatures(&self, ctx: &dyn Context, tx: &dyn Tx) -> Res<Box<[Condition]>> {
         let header = ctx.get(&BLOCK_HEADER)?;
         let chain_id = &header.chain_id;
         let sign_bytes = tx.get_sign_bytes();
@@ -47,7 +53,7 @@ impl Keeper {
         Ok(Box::from(signers))
     }
 
-    fn verify_signature(&self, ctx: &SimpleContext, sig: &dyn StdSignature, sign_bytes: &[u8], chain_id: &str) -> Res<Condition> {
+    fn verify_signature(&self, ctx: &dyn Context, sig: &dyn StdSignature, sign_bytes: &[u8], chain_id: &str) -> Res<Condition> {
         let cond = sig.get_pub_key().condition();
         let addr = condition_address(ctx, &cond);
         let acc = self.get_or_create_account(ctx, &addr);
@@ -57,13 +63,13 @@ impl Keeper {
         if !pk.verify(to_sign.as_ref(), sig.get_signature()) {
             panic!()
         }
-        let mut new_acc = acc.check_and_increment_sequence(seq)?;
-        self.auth_table.save(ctx, &mut new_acc);
+        let new_acc = acc.check_and_increment_sequence(seq)?;
+        self.auth_table.save(&store_context(ctx)?, &new_acc)?;
         Ok(cond)
     }
 
-    fn get_or_create_account(&self, ctx: &SimpleContext, addr: &Address) -> Account {
-        match self.auth_table.get_one(ctx, addr) {
+    fn get_or_create_account(&self, ctx: &dyn Context, addr: &Address) -> Account {
+        match store_context(ctx).and_then(|stores| self.auth_table.get_one(&stores, &Vec::from(addr.0.clone()))) {
             Err(e) => Account {
                 address: Vec::from(addr.0.clone()),
                 pubkey: Default::default(),
@@ -72,7 +78,7 @@ impl Keeper {
                 unknown_fields: Default::default(),
                 cached_size: Default::default(),
             },
-            Ok(acct) => *acct.value().clone()
+            Ok((_, acct)) => acct
         }
     }
 }
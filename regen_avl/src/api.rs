@@ -1,6 +1,8 @@
 use std::sync::Arc;
+use std::cell::RefCell;
 use std::cmp::{Ordering};
-use regen_store::{MutableMap, Result};
+use regen_store::Result;
+use regen_store::multi::KVStore;
 
 pub trait Reader<T> {
     fn read(&self, buf: &[u8]) -> Result<T>;
@@ -26,8 +28,10 @@ pub struct TreeContext<K, V> {
     pub comparator: fn(&K, &K) -> Ordering,
 }
 
+/// Persists the nodes and commits of a tree in a backing store, which is committed along with
+/// the tree.
 pub struct NodeStore<K, V> {
-    pub store: Box<dyn MutableMap<Vec<u8>, Vec<u8>>>,
+    pub store: RefCell<Box<KVStore>>,
     pub key_marshaller: Box<dyn Marshaller<K>>,
     pub value_marshaller: Box<dyn Marshaller<V>>,
 }
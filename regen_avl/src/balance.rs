@@ -13,14 +13,6 @@ impl<K, V> TreeContext<K, V> {
             }
         }
     }
-    pub(crate) fn get_store_mut(&mut self) -> Result<&mut NodeStore<K, V>> {
-        match &mut self.store {
-            None => Err(Box::from(StoreError::Other(String::from("have a HashRef but no backing store!")))),
-            Some(store) => {
-                Ok(store.as_mut())
-            }
-        }
-    }
 }
 
 impl<K, V> NodeRef<K, V> {
@@ -65,8 +57,8 @@ fn make_node<K: Clone, V: Clone>(key: &K, value: &V, left: Option<Arc<Node<K, V>
         data: Arc::new(NodeData {
             key: key.clone(),
             value: value.clone(),
-            height: (max(node_height(&left), node_height(&right))) as u32,
-            rank: node_rank(&left) + node_rank(&left) + 1,
+            height: (max(node_height(&left), node_height(&right)) + 1) as u32,
+            rank: node_rank(&left) + node_rank(&right) + 1,
         }),
         left: make_node_ref(left),
         right: make_node_ref(right),
@@ -198,7 +190,7 @@ impl<K: Clone, V: Clone> Node<K, V> {
         Node {
             data: Arc::new(NodeData {
                 key: data.key.clone(),
-                value: data.value.clone(),
+                value: v.clone(),
                 height: data.height,
                 rank: data.rank,
             }),
@@ -236,3 +228,42 @@ pub(crate) fn insert<K: Clone, V: Clone>(node: Option<Arc<Node<K, V>>>, ctx: &Tr
 }
 
 
+
+fn remove_min<K: Clone, V: Clone>(node: &Arc<Node<K, V>>, ctx: &TreeContext<K, V>) -> Result<(Arc<NodeData<K, V>>, Option<Arc<Node<K, V>>>)> {
+    let right = node.right.get_node(ctx)?;
+    match node.left.get_node(ctx)? {
+        None => Ok((node.data.clone(), right)),
+        Some(left) => {
+            let (min, new_left) = remove_min(&left, ctx)?;
+            let data = &node.data;
+            Ok((min, Some(make_node(&data.key, &data.value, new_left, right).balance(ctx)?)))
+        }
+    }
+}
+
+pub(crate) fn remove<K: Clone, V: Clone>(node: Option<Arc<Node<K, V>>>, ctx: &TreeContext<K, V>, key: &K) -> Result<Option<Arc<Node<K, V>>>> {
+    match node {
+        None => Ok(None),
+        Some(node) => {
+            let data = &node.data;
+            let left = node.left.get_node(ctx)?;
+            let right = node.right.get_node(ctx)?;
+            match (ctx.comparator)(key, &data.key) {
+                Ordering::Less => {
+                    Ok(Some(make_node(&data.key, &data.value, remove(left, ctx, key)?, right).balance(ctx)?))
+                }
+                Ordering::Greater => {
+                    Ok(Some(make_node(&data.key, &data.value, left, remove(right, ctx, key)?).balance(ctx)?))
+                }
+                Ordering::Equal => match (left, right) {
+                    (None, right) => Ok(right),
+                    (left, None) => Ok(left),
+                    (left, Some(right)) => {
+                        let (min, new_right) = remove_min(&right, ctx)?;
+                        Ok(Some(make_node(&min.key, &min.value, left, new_right).balance(ctx)?))
+                    }
+                }
+            }
+        }
+    }
+}
@@ -17,3 +17,19 @@ pub fn find_node<K, V>(node: &Arc<Node<K, V>>, ctx: &TreeContext<K, V>, key: &K)
         Ordering::Equal => Some(node.clone())
     })
 }
+
+/// Returns the number of keys in the tree less than `key`, using the subtree sizes kept in
+/// `NodeData::rank`.
+pub fn rank<K, V>(node: Option<Arc<Node<K, V>>>, ctx: &TreeContext<K, V>, key: &K) -> Result<u64> {
+    let mut node = node;
+    let mut rank = 0;
+    while let Some(n) = node {
+        if (ctx.comparator)(&n.data.key, key) == Ordering::Less {
+            rank += n.left.get_node(ctx)?.map_or(0, |l| l.data.rank) + 1;
+            node = n.right.get_node(ctx)?;
+        } else {
+            node = n.left.get_node(ctx)?;
+        }
+    }
+    Ok(rank)
+}
@@ -1,12 +1,13 @@
 use crate::api::{Node, TreeContext, NodeRef, NodeData, NodeStore};
-use regen_store::{MutableMap, Map, Result};
+use std::convert::TryInto;
+use regen_store::{MutableMap, Map, Batch, CommitKVStore, Result, StoreError};
 use std::sync::Arc;
 use crate::api::NodeRef::{HashRef, MemRef, NoRef};
 use crate::codec;
 use protobuf::Message;
 
 impl<K, V> Node<K, V> {
-    fn calc_hash_serialize(&self, ctx: &mut TreeContext<K, V>, serialize: bool) -> Result<Option<Self>> {
+    fn calc_hash_serialize(&self, ctx: &TreeContext<K, V>, serialize: bool) -> Result<Option<Self>> {
         match &self.hash {
             // hash is already calculated
             Some(h) => {
@@ -23,10 +24,10 @@ impl<K, V> Node<K, V> {
                                 right: new_right.unwrap_or_else(|| self.right.clone()),
                                 hash: Some(h.clone()),
                             };
-                            ctx.get_store_mut()?.set(&h, &new_node)?;
+                            ctx.get_store()?.set(&h, &new_node)?;
                             return Ok(Some(new_node));
                         } else {
-                            ctx.get_store_mut()?.set(&h, self)?;
+                            ctx.get_store()?.set(&h, self)?;
                             return Ok(None);
                         }
                     }
@@ -55,7 +56,7 @@ impl<K, V> Node<K, V> {
             hash: Some(hash.clone()),
         };
         if serialize {
-            ctx.get_store_mut()?.set(&hash, &new_node)?;
+            ctx.get_store()?.set(&hash, &new_node)?;
         }
         Ok(Some(new_node))
     }
@@ -78,7 +79,7 @@ impl<K, V> Clone for NodeRef<K, V> {
 const EMPTY: Vec<u8> = Vec::new();
 
 impl<K, V> NodeRef<K, V> {
-    fn get_hash(&self) -> Vec<u8> {
+    pub(crate) fn get_hash(&self) -> Vec<u8> {
         match self {
             NodeRef::HashRef(h) => h.clone(),
             NodeRef::MemRef(node) => node.hash.clone().unwrap_or(EMPTY),
@@ -86,7 +87,7 @@ impl<K, V> NodeRef<K, V> {
         }
     }
 
-    fn calc_hash_serialize(&self, ctx: &mut TreeContext<K, V>, serialize: bool) -> Result<Option<NodeRef<K, V>>> {
+    pub(crate) fn calc_hash_serialize(&self, ctx: &TreeContext<K, V>, serialize: bool) -> Result<Option<NodeRef<K, V>>> {
         match self {
             NodeRef::HashRef(_) => Ok(None),
             NodeRef::MemRef(node) => {
@@ -132,7 +133,7 @@ fn read_node_ref<K, V>(hash: Vec<u8>) -> NodeRef<K, V> {
 
 impl<K, V> NodeStore<K, V> {
     pub fn get(&self, hash: &Vec<u8>) -> Result<Option<Node<K, V>>> {
-        let res = self.store.get(&node_hash__node__key(hash))?;
+        let res = self.store.borrow().get(&node_hash__node__key(hash))?;
         match res {
             None => Ok(None),
             Some(bytes) => {
@@ -162,12 +163,12 @@ impl<K, V> NodeStore<K, V> {
     }
 
     pub fn has(&self, hash: &Vec<u8>) -> Result<bool> {
-        self.has(&node_hash__node__key(hash))
+        self.store.borrow().has(&node_hash__node__key(hash))
     }
 }
 
 impl<K, V> NodeStore<K, V> {
-    pub fn set(&mut self, key: &Vec<u8>, value: &Node<K, V>) -> Result<()> {
+    pub fn set(&self, key: &Vec<u8>, value: &Node<K, V>) -> Result<()> {
         let data = &value.data;
         let key_bytes = self.key_marshaller.write(&data.key);
         let value_bytes = self.value_marshaller.write(&data.value);
@@ -182,12 +183,59 @@ impl<K, V> NodeStore<K, V> {
             cached_size: Default::default(),
         };
         let proto_bytes = proto_node.write_to_bytes()?;
-        self.store.set(&node_hash__node__key(key), &proto_bytes)
+        self.store.borrow_mut().set(&node_hash__node__key(key), &proto_bytes)
     }
 
-    pub fn delete(&mut self, key: &Vec<u8>) -> Result<()> {
-        self.store.delete(&node_hash__node__key(key))
+    pub fn delete(&self, key: &Vec<u8>) -> Result<()> {
+        self.store.borrow_mut().delete(&node_hash__node__key(key))
     }
 }
 
 
+
+const VERSION_COMMIT_PREFIX: u8 = 1;
+
+const LATEST_VERSION_KEY: [u8; 1] = [2];
+
+fn version__commit__key(version: u64) -> Vec<u8> {
+    let mut res = Vec::with_capacity(9);
+    res.push(VERSION_COMMIT_PREFIX);
+    res.extend_from_slice(&version.to_be_bytes());
+    res
+}
+
+impl<K, V> NodeStore<K, V> {
+    pub(crate) fn get_commit(&self, version: u64) -> Result<Option<codec::Commit>> {
+        match self.store.borrow().get(&version__commit__key(version))? {
+            None => Ok(None),
+            Some(bytes) => Ok(Some(protobuf::parse_from_bytes(&bytes)?)),
+        }
+    }
+
+    pub(crate) fn set_commit(&self, commit: &codec::Commit) -> Result<()> {
+        let mut store = self.store.borrow_mut();
+        store.set(&version__commit__key(commit.height), &commit.write_to_bytes()?)?;
+        store.set(&Vec::from(LATEST_VERSION_KEY), &Vec::from(commit.height.to_be_bytes()))
+    }
+
+    pub(crate) fn delete_commit(&self, version: u64) -> Result<()> {
+        self.store.borrow_mut().delete(&version__commit__key(version))
+    }
+
+    pub(crate) fn latest_version(&self) -> Result<u64> {
+        match self.store.borrow().get(&Vec::from(LATEST_VERSION_KEY))? {
+            None => Ok(0),
+            Some(bytes) => match bytes.as_slice().try_into() {
+                Ok(buf) => Ok(u64::from_be_bytes(buf)),
+                Err(_) => Err(Box::from(StoreError::Other(format!("invalid latest version length {}", bytes.len())))),
+            },
+        }
+    }
+
+    pub(crate) fn commit(&self) -> Result<()> {
+        let mut store = self.store.borrow_mut();
+        store.write()?;
+        store.commit()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,90 @@
+use std::cmp::Ordering;
+use std::sync::Arc;
+use crate::api::{Node, NodeRef, TreeContext};
+use regen_store::{Iterator, Result};
+
+/// In-order iteration over `start` inclusive to `end` exclusive, keeping the path to the next
+/// node on a stack so only `O(log n)` nodes are held at a time.
+pub(crate) struct RangeIter<K, V> {
+    ctx: Arc<TreeContext<K, V>>,
+    stack: Vec<Arc<Node<K, V>>>,
+    start: Option<K>,
+    end: Option<K>,
+    reverse: bool,
+}
+
+impl<K: Clone, V: Clone> RangeIter<K, V> {
+    pub(crate) fn new(ctx: Arc<TreeContext<K, V>>, root: &NodeRef<K, V>, start: Option<&K>, end: Option<&K>, reverse: bool) -> Result<Self> {
+        let mut iter = RangeIter {
+            ctx,
+            stack: Vec::new(),
+            start: start.cloned(),
+            end: end.cloned(),
+            reverse,
+        };
+        let root = root.get_node(&iter.ctx)?;
+        iter.seek(root)?;
+        Ok(iter)
+    }
+
+    fn before_start(&self, key: &K) -> bool {
+        match &self.start {
+            None => false,
+            Some(start) => (self.ctx.comparator)(key, start) == Ordering::Less,
+        }
+    }
+
+    fn after_end(&self, key: &K) -> bool {
+        match &self.end {
+            None => false,
+            Some(end) => (self.ctx.comparator)(key, end) != Ordering::Less,
+        }
+    }
+
+    // pushes the path from node down to the first node in iteration order, skipping subtrees
+    // which are out of range
+    fn seek(&mut self, mut node: Option<Arc<Node<K, V>>>) -> Result<()> {
+        while let Some(n) = node {
+            if self.reverse {
+                if self.after_end(&n.data.key) {
+                    node = n.left.get_node(&self.ctx)?;
+                } else {
+                    node = n.right.get_node(&self.ctx)?;
+                    self.stack.push(n);
+                }
+            } else if self.before_start(&n.data.key) {
+                node = n.right.get_node(&self.ctx)?;
+            } else {
+                node = n.left.get_node(&self.ctx)?;
+                self.stack.push(n);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<K: Clone, V: Clone> Iterator<K, V> for RangeIter<K, V> {
+    fn next(&mut self) -> Result<Option<(K, V)>> {
+        let node = match self.stack.pop() {
+            None => return Ok(None),
+            Some(node) => node,
+        };
+        let data = &node.data;
+        let done = if self.reverse {
+            self.before_start(&data.key)
+        } else {
+            self.after_end(&data.key)
+        };
+        if done {
+            self.stack.clear();
+            return Ok(None);
+        }
+        let next = if self.reverse {
+            node.left.get_node(&self.ctx)?
+        } else {
+            node.right.get_node(&self.ctx)?
+        };
+        self.seek(next)?;
+        Ok(Some((data.key.clone(), data.value.clone())))
+    }
+}
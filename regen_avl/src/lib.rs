@@ -1,8 +1,10 @@
 mod lru;
 mod codec;
-mod api;
+pub mod api;
 mod balance;
 mod find;
 mod hash_serialize;
 pub mod tree;
+pub mod store;
+mod iter;
 
@@ -0,0 +1,209 @@
+use std::collections::BTreeMap;
+use std::ops::Range;
+use std::sync::Arc;
+use regen_store::{Map, OrderedMap, MutableMap, MutableOrderedMap, Batch, CommitKVStore, CommitID, Iterator, Result, StoreError,
+                  VersionedMap, VersionedOrderedMap, PrunableVersionedMap};
+use regen_store::cache::CacheBatch;
+use crate::api::{TreeContext, NodeRef};
+use crate::api::NodeRef::{HashRef, MemRef, NoRef};
+use crate::balance::{insert, remove};
+use crate::find::{find_node, rank};
+use crate::iter::RangeIter;
+use crate::codec;
+
+/// A `CommitKVStore` over an AVL tree whose older versions can still be read. When the
+/// `TreeContext` has a `NodeStore`, nodes and commits are persisted there on `commit`, which also
+/// commits the backing store, and older versions are read back from it. Otherwise the in-memory
+/// root of every version is kept until `delete_versions` drops it.
+pub struct CommitStore<K, V> {
+    ctx: Arc<TreeContext<K, V>>,
+    working: NodeRef<K, V>,
+    // only used without a NodeStore
+    roots: BTreeMap<u64, NodeRef<K, V>>,
+    latest: CommitID,
+}
+
+impl<K: Clone, V: Clone> CommitStore<K, V> {
+    pub fn new(ctx: Arc<TreeContext<K, V>>) -> Result<Self> {
+        let mut working = NoRef;
+        let mut latest = CommitID::default();
+        if let Some(store) = &ctx.store {
+            let version = store.latest_version()?;
+            if let Some(commit) = store.get_commit(version)? {
+                working = read_root(commit.root_node_hash.clone());
+                latest = CommitID { version, hash: commit.root_node_hash };
+            }
+        }
+        Ok(CommitStore { ctx, working, roots: BTreeMap::new(), latest })
+    }
+
+    fn root_at(&self, version: u64) -> Result<NodeRef<K, V>> {
+        if version > self.latest.version {
+            return Err(version_not_found(version));
+        }
+        if let Some(root) = self.roots.get(&version) {
+            return Ok(root.clone());
+        }
+        match &self.ctx.store {
+            None => Err(version_not_found(version)),
+            Some(store) => match store.get_commit(version)? {
+                None => Err(version_not_found(version)),
+                Some(commit) => Ok(read_root(commit.root_node_hash)),
+            }
+        }
+    }
+
+    fn get_in(&self, root: &NodeRef<K, V>, key: &K) -> Result<Option<V>> {
+        Ok(match root.get_node(&self.ctx)? {
+            None => None,
+            Some(node) => find_node(&node, &self.ctx, key)?.map(|n| n.data.value.clone()),
+        })
+    }
+}
+
+fn read_root<K, V>(hash: Vec<u8>) -> NodeRef<K, V> {
+    if hash.is_empty() {
+        NoRef
+    } else {
+        HashRef(hash)
+    }
+}
+
+fn version_not_found(version: u64) -> Box<dyn std::error::Error> {
+    Box::from(StoreError::Other(format!("version {} not found", version)))
+}
+
+impl<K: Clone, V: Clone> Map<K, V> for CommitStore<K, V> {
+    fn get(&self, key: &K) -> Result<Option<V>> {
+        self.get_in(&self.working, key)
+    }
+
+    fn has(&self, key: &K) -> Result<bool> {
+        Ok(self.get(key)?.is_some())
+    }
+}
+
+impl<K: Clone, V: Clone> OrderedMap<K, V> for CommitStore<K, V> {
+    fn iterator(&self, start: Option<&K>, end: Option<&K>) -> Result<Box<dyn Iterator<K, V> + '_>> {
+        Ok(Box::new(RangeIter::new(self.ctx.clone(), &self.working, start, end, false)?))
+    }
+
+    fn reverse_iterator(&self, start: Option<&K>, end: Option<&K>) -> Result<Box<dyn Iterator<K, V> + '_>> {
+        Ok(Box::new(RangeIter::new(self.ctx.clone(), &self.working, start, end, true)?))
+    }
+
+    fn count(&self, start: Option<&K>, end: Option<&K>) -> Result<u64> {
+        let root = self.working.get_node(&self.ctx)?;
+        let below_end = match end {
+            None => root.as_ref().map_or(0, |n| n.data.rank),
+            Some(end) => rank(root.clone(), &self.ctx, end)?,
+        };
+        let below_start = match start {
+            None => 0,
+            Some(start) => rank(root, &self.ctx, start)?,
+        };
+        Ok(below_end.saturating_sub(below_start))
+    }
+}
+
+impl<K: Clone, V: Clone> MutableMap<K, V> for CommitStore<K, V> {
+    fn set(&mut self, key: &K, value: &V) -> Result<()> {
+        let root = insert(self.working.get_node(&self.ctx)?, &self.ctx, key, value)?;
+        self.working = MemRef(root);
+        Ok(())
+    }
+
+    fn delete(&mut self, key: &K) -> Result<()> {
+        if !self.has(key)? {
+            return Ok(());
+        }
+        self.working = match remove(self.working.get_node(&self.ctx)?, &self.ctx, key)? {
+            None => NoRef,
+            Some(root) => MemRef(root),
+        };
+        Ok(())
+    }
+}
+
+impl<K: Clone, V: Clone> MutableOrderedMap<K, V> for CommitStore<K, V> {}
+
+impl<K: Clone + Ord, V: Clone> Batch<K, V> for CommitStore<K, V> {
+    fn new_batch(&mut self) -> Box<dyn Batch<K, V> + '_> {
+        Box::new(CacheBatch::new(self))
+    }
+
+    fn write(&mut self) -> Result<()> {
+        // the working tree is updated in place
+        Ok(())
+    }
+}
+
+impl<K: Clone + Ord, V: Clone> CommitKVStore<K, V, CommitID> for CommitStore<K, V> {
+    fn commit(&mut self) -> Result<CommitID> {
+        let serialize = self.ctx.store.is_some();
+        if let Some(root) = self.working.calc_hash_serialize(&self.ctx, serialize)? {
+            self.working = root;
+        }
+        let version = self.latest.version + 1;
+        let hash = self.working.get_hash();
+        if let Some(store) = &self.ctx.store {
+            store.set_commit(&codec::Commit {
+                parent_commit_hash: self.latest.hash.clone(),
+                root_node_hash: hash.clone(),
+                height: version,
+                unknown_fields: Default::default(),
+                cached_size: Default::default(),
+            })?;
+            store.commit()?;
+        } else {
+            self.roots.insert(version, self.working.clone());
+        }
+        self.latest = CommitID { version, hash };
+        Ok(self.latest.clone())
+    }
+}
+
+impl<K: Clone, V: Clone> VersionedMap<K, V> for CommitStore<K, V> {
+    fn latest_version(&self) -> u64 {
+        self.latest.version
+    }
+
+    fn get_at(&self, version: u64, key: &K) -> Result<Option<V>> {
+        let root = self.root_at(version)?;
+        self.get_in(&root, key)
+    }
+
+    fn has_at(&self, version: u64, key: &K) -> Result<bool> {
+        Ok(self.get_at(version, key)?.is_some())
+    }
+}
+
+impl<K: Clone, V: Clone> VersionedOrderedMap<K, V> for CommitStore<K, V> {
+    fn iterator_at(&self, version: u64, start: Option<&K>, end: Option<&K>) -> Result<Box<dyn Iterator<K, V> + '_>> {
+        let root = self.root_at(version)?;
+        Ok(Box::new(RangeIter::new(self.ctx.clone(), &root, start, end, false)?))
+    }
+
+    fn reverse_iterator_at(&self, version: u64, start: Option<&K>, end: Option<&K>) -> Result<Box<dyn Iterator<K, V> + '_>> {
+        let root = self.root_at(version)?;
+        Ok(Box::new(RangeIter::new(self.ctx.clone(), &root, start, end, true)?))
+    }
+}
+
+impl<K: Clone, V: Clone> PrunableVersionedMap<K, V> for CommitStore<K, V> {
+    // Only the version roots are dropped, nodes which are no longer reachable stay in the
+    // NodeStore until there is reference counting to clean them up.
+    fn delete_versions(&mut self, versions: Range<u64>) -> Result<()> {
+        if versions.contains(&self.latest.version) {
+            return Err(Box::from(StoreError::Other(String::from("can't delete the latest version"))));
+        }
+        let end = versions.end.min(self.latest.version);
+        for version in versions.start..end {
+            self.roots.remove(&version);
+            if let Some(store) = &self.ctx.store {
+                store.delete_commit(version)?;
+            }
+        }
+        Ok(())
+    }
+}
@@ -3,7 +3,7 @@ use regen_store::{Map, Result, PersistentMap};
 use std::error::Error;
 use crate::find::find_node;
 use std::sync::Arc;
-use crate::balance::insert;
+use crate::balance::{insert, remove};
 use crate::api::NodeRef::{MemRef, NoRef};
 
 pub struct Tree<K, V> {
@@ -44,6 +44,12 @@ impl<K: 'static + Clone, V: 'static + Clone> PersistentMap<K, V> for Tree<K, V>
     }
 
     fn without(&self, key: &K) -> Result<Box<dyn PersistentMap<K, V>>> {
-        unimplemented!()
+        Ok(Box::from(Tree {
+            ctx: self.ctx.clone(),
+            root: match remove(self.root.get_node(&self.ctx)?, &self.ctx, key)? {
+                None => NoRef,
+                Some(root) => MemRef(root),
+            },
+        }))
     }
 }
@@ -0,0 +1,153 @@
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::Hasher as StdHasher;
+use std::sync::Arc;
+use proptest::prelude::*;
+use regen_avl::api::{Reader, Writer, Marshaller, Hasher, TreeContext, NodeStore};
+use regen_avl::store::CommitStore;
+use regen_store::mem::MemStore;
+use regen_store_test::store_conformance_tests;
+use regen_store::{Map, MutableMap, OrderedMap, CommitKVStore, VersionedMap, VersionedOrderedMap, PrunableVersionedMap, Result};
+
+struct Bytes;
+
+impl Reader<Vec<u8>> for Bytes {
+    fn read(&self, buf: &[u8]) -> Result<Vec<u8>> {
+        Ok(Vec::from(buf))
+    }
+}
+
+impl Writer<Vec<u8>> for Bytes {
+    fn write(&self, k: &Vec<u8>) -> Vec<u8> {
+        k.clone()
+    }
+}
+
+impl Marshaller<Vec<u8>> for Bytes {}
+
+struct TestHasher(RefCell<DefaultHasher>);
+
+impl Hasher for TestHasher {
+    fn input(&self, bytes: &[u8]) {
+        self.0.borrow_mut().write(bytes)
+    }
+
+    fn result(&self) -> Vec<u8> {
+        self.0.borrow().finish().to_be_bytes().to_vec()
+    }
+
+    fn output_size(&self) -> usize {
+        8
+    }
+}
+
+fn new_store(persist: bool) -> CommitStore<Vec<u8>, Vec<u8>> {
+    new_store_on(if persist { Some(MemStore::new()) } else { None }).unwrap()
+}
+
+fn new_store_on(backing: Option<MemStore>) -> Result<CommitStore<Vec<u8>, Vec<u8>>> {
+    let store = if let Some(backing) = backing {
+        Some(Box::new(NodeStore {
+            store: RefCell::new(Box::new(backing)),
+            key_marshaller: Box::new(Bytes),
+            value_marshaller: Box::new(Bytes),
+        }))
+    } else {
+        None
+    };
+    CommitStore::new(Arc::new(TreeContext {
+        key_to_canonical_bytes: Box::new(Bytes),
+        value_to_canonical_bytes: Box::new(Bytes),
+        new_digest: || Box::new(TestHasher(RefCell::new(DefaultHasher::new()))),
+        store,
+        comparator: |a: &Vec<u8>, b: &Vec<u8>| a.cmp(b),
+    }))
+}
+
+store_conformance_tests!(|| new_store(true));
+
+fn collect(mut it: Box<dyn regen_store::Iterator<Vec<u8>, Vec<u8>> + '_>) -> Vec<(Vec<u8>, Vec<u8>)> {
+    let mut res = Vec::new();
+    while let Some(kv) = it.next().unwrap() {
+        res.push(kv);
+    }
+    res
+}
+
+#[test]
+fn test_versions() {
+    for persist in [false, true].iter() {
+        let mut store = new_store(*persist);
+        let (a, b) = (b"a".to_vec(), b"b".to_vec());
+        store.set(&a, &b"1".to_vec()).unwrap();
+        let c1 = store.commit().unwrap();
+        store.set(&a, &b"2".to_vec()).unwrap();
+        store.set(&b, &b"3".to_vec()).unwrap();
+        let c2 = store.commit().unwrap();
+        store.delete(&a).unwrap();
+        let c3 = store.commit().unwrap();
+        assert_eq!((c1.version, c2.version, c3.version), (1, 2, 3));
+        assert_ne!(c1.hash, c2.hash);
+        assert_eq!(store.latest_version(), 3);
+
+        assert_eq!(store.get_at(1, &a).unwrap(), Some(b"1".to_vec()));
+        assert_eq!(store.get_at(2, &a).unwrap(), Some(b"2".to_vec()));
+        assert!(!store.has_at(3, &a).unwrap());
+        assert!(!store.has(&a).unwrap());
+        assert!(store.get_at(4, &a).is_err());
+
+        assert_eq!(collect(store.iterator_at(2, None, None).unwrap()).len(), 2);
+        assert_eq!(store.count(None, None).unwrap(), 1);
+        assert_eq!(store.count(Some(&b), None).unwrap(), 1);
+        assert_eq!(store.count(None, Some(&b)).unwrap(), 0);
+        assert_eq!(collect(store.reverse_iterator_at(1, None, None).unwrap()), vec![(a.clone(), b"1".to_vec())]);
+
+        assert!(store.delete_versions(1..4).is_err());
+        store.delete_versions(1..2).unwrap();
+        assert!(store.get_at(1, &a).is_err());
+        assert_eq!(store.get_at(2, &a).unwrap(), Some(b"2".to_vec()));
+    }
+}
+
+#[test]
+fn test_invalid_latest_version() {
+    let mut backing = MemStore::new();
+    backing.set(&vec![2], &vec![0, 1]).unwrap();
+    assert!(new_store_on(Some(backing)).is_err());
+}
+
+proptest! {
+    #[test]
+    fn test_matches_btree_map(ops in prop::collection::vec((any::<u8>(), any::<u8>(), any::<bool>()), 1..200),
+                              start in any::<u8>(), end in any::<u8>()) {
+        let mut store = new_store(false);
+        let mut model = BTreeMap::new();
+        for (k, v, set) in ops.iter() {
+            if *set {
+                store.set(&vec![*k], &vec![*v]).unwrap();
+                model.insert(vec![*k], vec![*v]);
+            } else {
+                store.delete(&vec![*k]).unwrap();
+                model.remove(&vec![*k]);
+            }
+        }
+        let expected: Vec<_> = model.iter().map(|(k, v)| (k.clone(), v.clone())).collect();
+        prop_assert_eq!(collect(store.iterator(None, None).unwrap()), expected.clone());
+        let mut reversed = expected;
+        reversed.reverse();
+        prop_assert_eq!(collect(store.reverse_iterator(None, None).unwrap()), reversed);
+
+        let (start, end) = (vec![start], vec![end]);
+        let ranged: Vec<_> = model.iter()
+            .filter(|(k, _)| **k >= start && **k < end)
+            .map(|(k, v)| (k.clone(), v.clone()))
+            .collect();
+        prop_assert_eq!(collect(store.iterator(Some(&start), Some(&end)).unwrap()), ranged.clone());
+        prop_assert_eq!(store.count(Some(&start), Some(&end)).unwrap(), ranged.len() as u64);
+        prop_assert_eq!(store.count(None, Some(&end)).unwrap(), model.range(..end.clone()).count() as u64);
+        let mut reversed = ranged;
+        reversed.reverse();
+        prop_assert_eq!(collect(store.reverse_iterator(Some(&start), Some(&end)).unwrap()), reversed);
+    }
+}
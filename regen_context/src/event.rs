@@ -0,0 +1,107 @@
+use std::cell::RefCell;
+use crate::{context_key, Context, ContextError};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Attribute {
+    pub key: String,
+    pub value: String,
+    /// Asks indexers to index the events by this attribute. The attributes of ABCI 0.6 events,
+    /// which are what `regen_abci` turns events into, have no such flag, so there it is dropped
+    /// and the node's `index_tags` setting decides what gets indexed instead.
+    pub index: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Event {
+    pub event_type: String,
+    pub attributes: Vec<Attribute>,
+}
+
+impl Event {
+    pub fn new(event_type: &str) -> Self {
+        Event { event_type: String::from(event_type), attributes: Vec::new() }
+    }
+
+    pub fn attr(mut self, key: &str, value: &str) -> Self {
+        self.attributes.push(Attribute { key: String::from(key), value: String::from(value), index: false });
+        self
+    }
+
+    pub fn indexed_attr(mut self, key: &str, value: &str) -> Self {
+        self.attributes.push(Attribute { key: String::from(key), value: String::from(value), index: true });
+        self
+    }
+
+    /// The attributes as the key/value pairs of an ABCI 0.6 event, see `Attribute::index`.
+    pub fn kv_pairs(&self) -> Vec<(Vec<u8>, Vec<u8>)> {
+        self.attributes.iter().map(|attr| (Vec::from(attr.key.as_bytes()), Vec::from(attr.value.as_bytes()))).collect()
+    }
+}
+
+/// A value which modules emit as an `Event`.
+pub trait TypedEvent {
+    fn to_event(&self) -> Event;
+}
+
+/// Collects the events emitted during an ABCI phase, see `EVENTS`.
+#[derive(Debug, Default)]
+pub struct EventManager(RefCell<Vec<Event>>);
+
+impl EventManager {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn emit(&self, event: Event) {
+        self.0.borrow_mut().push(event)
+    }
+
+    pub fn emit_typed(&self, event: &dyn TypedEvent) {
+        self.emit(event.to_event())
+    }
+
+    /// Returns the events collected so far, leaving the manager empty.
+    pub fn take(&self) -> Vec<Event> {
+        self.0.replace(Vec::new())
+    }
+}
+
+context_key!(
+    /// The `EventManager` of the current ABCI phase.
+    pub EVENTS: EventManager, debug
+);
+
+/// Emits `event` into the `EventManager` of `ctx`.
+pub fn emit(ctx: &dyn Context, event: Event) -> Result<(), ContextError> {
+    EVENTS.get(ctx)?.emit(event);
+    Ok(())
+}
+
+/// Runs a sub-execution with an `EventManager` of its own, adding its events to the one of
+/// `ctx`, if any, only if it succeeds. Sub-executions can be nested.
+pub fn with_events<R, E, F>(ctx: &dyn Context, f: F) -> Result<R, E>
+    where F: FnOnce(&dyn Context) -> Result<R, E> {
+    let sub = ctx.with(&EVENTS, EventManager::new());
+    let res = f(sub.as_ref());
+    if res.is_ok() {
+        if let (Ok(events), Ok(parent)) = (EVENTS.get(sub.as_ref()), EVENTS.get(ctx)) {
+            for event in events.take() {
+                parent.emit(event);
+            }
+        }
+    }
+    res
+}
+
+/// Runs an ABCI phase with an `EventManager` of its own, returning its result along with the
+/// events it emitted, or none if `succeeded` is false for the result.
+pub fn collect_events<R, F, S>(ctx: &dyn Context, f: F, succeeded: S) -> (R, Vec<Event>)
+    where F: FnOnce(&dyn Context) -> R, S: FnOnce(&R) -> bool {
+    let phase = ctx.with(&EVENTS, EventManager::new());
+    let res = f(phase.as_ref());
+    let events = match EVENTS.get(phase.as_ref()) {
+        Ok(events) if succeeded(&res) => events.take(),
+        _ => Vec::new(),
+    };
+    (res, events)
+}
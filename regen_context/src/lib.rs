@@ -1,74 +1,253 @@
+// the impls generated by err_derive's `Error` derive are wrapped in a const
+#![allow(non_local_definitions)]
+
 use std::marker::PhantomData;
 use std::sync::Arc;
-use std::any::Any;
+use std::any::{type_name, Any, TypeId};
+use std::fmt::{self, Debug, Formatter};
+use std::hash::{Hash, Hasher};
 use err_derive::Error;
-use crate::ContextError::TypeConversionFailed;
+use crate::ContextError::{NotFound, TypeConversionFailed};
+
+pub mod event;
 
 #[derive(Debug, Error)]
 pub enum ContextError {
-    #[error(display="not found")]
-    NotFound,
+    #[error(display="key {} not found", key)]
+    NotFound{key: String},
     #[error(display="type conversion failed for key {:?}", key)]
     TypeConversionFailed{key: String},
 }
 
-pub struct ContextKey<T>(pub &'static str, pub PhantomData<T>);
+type DebugFn = fn(&dyn Any, &mut Formatter) -> fmt::Result;
+
+/// Identifies a `ContextKey` independently of its value type. Keys are compared by the
+/// `TypeId` of a marker type private to their `context_key!`, the name is only for messages.
+#[derive(Clone, Copy)]
+pub struct KeyId {
+    id: fn() -> TypeId,
+    name: &'static str,
+    type_name: fn() -> &'static str,
+    debug: Option<DebugFn>,
+}
+
+impl KeyId {
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /// The type of the values of the key.
+    pub fn type_name(&self) -> &'static str {
+        (self.type_name)()
+    }
+}
+
+impl PartialEq for KeyId {
+    fn eq(&self, other: &Self) -> bool {
+        (self.id)() == (other.id)()
+    }
+}
+
+impl Eq for KeyId {}
+
+impl Hash for KeyId {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        (self.id)().hash(state)
+    }
+}
+
+impl Debug for KeyId {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.write_str(self.name)
+    }
+}
+
+/// A typed key into a `Context`, declared with `context_key!`, its constructors are only for the
+/// macro. Two keys are never the same key, even if they have the same name and type.
+pub struct ContextKey<T> {
+    id: KeyId,
+    _value: PhantomData<T>,
+}
+
+impl<T> ContextKey<T> {
+    // Only for `context_key!`, which declares a fresh marker type `M` for each key. Two keys
+    // created with the same marker would be the same key.
+    #[doc(hidden)]
+    pub const fn new<M: 'static>(name: &'static str) -> Self {
+        ContextKey {
+            id: KeyId { id: TypeId::of::<M>, name, type_name: type_name::<T>, debug: None },
+            _value: PhantomData,
+        }
+    }
+
+    // Like `new`, for a key whose values are shown by the `Debug` output of contexts.
+    #[doc(hidden)]
+    pub const fn new_debug<M: 'static>(name: &'static str) -> Self where T: Debug + 'static {
+        ContextKey {
+            id: KeyId { id: TypeId::of::<M>, name, type_name: type_name::<T>, debug: Some(debug_value::<T>) },
+            _value: PhantomData,
+        }
+    }
+
+    pub fn id(&self) -> KeyId {
+        self.id
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.id.name
+    }
+}
+
+/// Declares a `ContextKey` constant named after its module path, for instance
+/// `context_key!(pub BLOCK_HEADER: Header);`. Keys declared with `debug`, like
+/// `context_key!(pub HEIGHT: u64, debug);`, show their values in the `Debug` output of contexts.
+#[macro_export]
+macro_rules! context_key {
+    ($(#[$attr:meta])* $vis:vis $name:ident: $t:ty) => {
+        $(#[$attr])*
+        $vis const $name: $crate::ContextKey<$t> = {
+            enum Marker {}
+            $crate::ContextKey::new::<Marker>(concat!(module_path!(), "::", stringify!($name)))
+        };
+    };
+    ($(#[$attr:meta])* $vis:vis $name:ident: $t:ty, debug) => {
+        $(#[$attr])*
+        $vis const $name: $crate::ContextKey<$t> = {
+            enum Marker {}
+            $crate::ContextKey::new_debug::<Marker>(concat!(module_path!(), "::", stringify!($name)))
+        };
+    };
+}
+
+fn debug_value<T: Debug + 'static>(value: &dyn Any, f: &mut Formatter) -> fmt::Result {
+    match value.downcast_ref::<T>() {
+        None => f.write_str("<invalid>"),
+        Some(value) => value.fmt(f),
+    }
+}
 
 impl <T: 'static> ContextKey<T> {
-    fn get(&self, ctx: &dyn Context2) -> Result<&T, ContextError> {
-        let any = ctx.get_raw(self.0)?;
+    pub fn get<'a>(&self, ctx: &'a dyn Context) -> Result<&'a T, ContextError> {
+        let any = ctx.get_raw(self.id)?;
         match any.downcast_ref::<T>() {
-            None => Err(TypeConversionFailed{key: String::from(self.0)}),
+            None => Err(TypeConversionFailed{key: String::from(self.id.name)}),
             Some(x) => Ok(x)
         }
     }
 
-    fn set(&self, ctx: &dyn Context2, value: T) -> &dyn Context2 {
-        unimplemented!()
+    pub fn set(&self, ctx: &dyn Context, value: T) -> Box<dyn Context> {
+        ctx.with_raw(self.id, Arc::new(value))
     }
 
-    fn unset(&self, ctx: &dyn Context2) -> &dyn Context2 {
-        unimplemented!()
+    pub fn unset(&self, ctx: &dyn Context) -> Box<dyn Context> {
+        ctx.without_raw(self.id)
     }
 }
 
-#[derive(Default, Clone)]
-pub struct SimpleContext(im::HashMap<String, Arc<dyn Any>>);
-
+/// An immutable map of typed values passed down through handlers. Contexts are never changed,
+/// `with_raw` and `without_raw` return a new context sharing the unchanged values, so a
+/// handler can hand an extended context to the next one without affecting its caller.
+///
+/// The typed methods are on `dyn Context` and `ContextKey`, so that handlers can take
+/// `&dyn Context`.
 pub trait Context {
-    fn get<T: 'static>(&self, key: &ContextKey<T>) -> Result<&T, ContextError>;
-    fn with<T: Any>(&self, key: &ContextKey<T>, value: T) -> Self;
-    fn without<T>(&self, key: &ContextKey<T>) -> Self;
+    fn get_raw(&self, key: KeyId) -> Result<&Arc<dyn Any>, ContextError>;
+    fn with_raw(&self, key: KeyId, value: Arc<dyn Any>) -> Box<dyn Context>;
+    fn without_raw(&self, key: KeyId) -> Box<dyn Context>;
+    /// The keys with a value, sorted by name.
+    fn keys(&self) -> Vec<KeyId>;
+}
+
+impl<'c> dyn Context + 'c {
+    pub fn get<T: 'static>(&self, key: &ContextKey<T>) -> Result<&T, ContextError> {
+        key.get(self)
+    }
+
+    pub fn with<T: 'static>(&self, key: &ContextKey<T>, value: T) -> Box<dyn Context> {
+        key.set(self, value)
+    }
+
+    pub fn without<T: 'static>(&self, key: &ContextKey<T>) -> Box<dyn Context> {
+        key.unset(self)
+    }
+
+    /// Gets the value of `key` along with a context without it, for values which should only
+    /// be used once, like the signers of a transaction.
+    pub fn take<T: 'static>(&self, key: &ContextKey<T>) -> Result<(&T, Box<dyn Context>), ContextError> {
+        Ok((key.get(self)?, key.unset(self)))
+    }
 }
 
-pub trait Context2 {
-    fn get_raw(&self, key: &str) -> Result<&Arc<dyn Any>, ContextError>;
-    fn with_raw(&self, key: &str, value: Box<dyn Any>) -> Box<dyn Context2>;
-    fn without_raw(&self, key: &str) -> Box<Context2>;
+/// Lists the keys with their types, and the values of keys declared with `debug`.
+impl<'c> Debug for dyn Context + 'c {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let mut map = f.debug_map();
+        for key in self.keys() {
+            map.entry(&format_args!("{}: {}", key.name, key.type_name()), &DebugValue(key, self.get_raw(key).ok()));
+        }
+        map.finish()
+    }
 }
 
+struct DebugValue<'a>(KeyId, Option<&'a Arc<dyn Any>>);
+
+impl<'a> Debug for DebugValue<'a> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match (self.0.debug, self.1) {
+            (Some(debug), Some(value)) => debug(value.as_ref(), f),
+            _ => f.write_str(".."),
+        }
+    }
+}
+
+#[derive(Default, Clone)]
+pub struct SimpleContext(im::HashMap<KeyId, Arc<dyn Any>>);
+
+/// `SimpleContext` also has the typed methods of `dyn Context`, returning a `SimpleContext`.
 impl SimpleContext {
     pub fn new() -> SimpleContext {
         SimpleContext(im::HashMap::new())
     }
+
+    pub fn get<T: 'static>(&self, key: &ContextKey<T>) -> Result<&T, ContextError> {
+        key.get(self)
+    }
+
+    pub fn with<T: 'static>(&self, key: &ContextKey<T>, value: T) -> Self {
+        SimpleContext(self.0.update(key.id, Arc::new(value)))
+    }
+
+    pub fn without<T: 'static>(&self, key: &ContextKey<T>) -> Self {
+        SimpleContext(self.0.without(&key.id))
+    }
+
+    pub fn take<T: 'static>(&self, key: &ContextKey<T>) -> Result<(&T, Self), ContextError> {
+        Ok((key.get(self)?, self.without(key)))
+    }
 }
 
 impl Context for SimpleContext {
-    fn get<T: 'static>(&self, key: &ContextKey<T>) -> Result<&T, ContextError> {
-        match self.0.get(key.0) {
-            None => Err(ContextError::NotFound),
-            Some(v) => match v.downcast_ref::<T>() {
-                None => Err(TypeConversionFailed{key: String::from(key.0)}),
-                Some(x) => Ok(x)
-            }
-        }
+    fn get_raw(&self, key: KeyId) -> Result<&Arc<dyn Any>, ContextError> {
+        self.0.get(&key).ok_or(NotFound{key: String::from(key.name)})
+    }
+
+    fn with_raw(&self, key: KeyId, value: Arc<dyn Any>) -> Box<dyn Context> {
+        Box::new(SimpleContext(self.0.update(key, value)))
+    }
+
+    fn without_raw(&self, key: KeyId) -> Box<dyn Context> {
+        Box::new(SimpleContext(self.0.without(&key)))
     }
 
-    fn with<T: Any>(&self, key: &ContextKey<T>, value: T) -> Self {
-        SimpleContext(self.0.update(String::from(key.0), Arc::from(value)))
+    fn keys(&self) -> Vec<KeyId> {
+        let mut keys: Vec<KeyId> = self.0.keys().cloned().collect();
+        keys.sort_by_key(|k| k.name);
+        keys
     }
+}
 
-    fn without<T>(&self, key: &ContextKey<T>) -> Self {
-        SimpleContext(self.0.without(key.0))
+impl Debug for SimpleContext {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        (self as &dyn Context).fmt(f)
     }
 }
@@ -0,0 +1,62 @@
+use std::any::type_name;
+use regen_context::{context_key, Context, ContextError, SimpleContext};
+
+context_key!(NAME: String);
+context_key!(HEIGHT: u64);
+
+mod other {
+    regen_context::context_key!(pub HEIGHT: u64);
+}
+
+// a handler only seeing the context as `&dyn Context`
+fn next_height(ctx: &dyn Context) -> Box<dyn Context> {
+    let height = *ctx.get(&HEIGHT).unwrap();
+    ctx.with(&HEIGHT, height + 1)
+}
+
+#[test]
+fn test_dyn_context() {
+    let ctx = SimpleContext::new().with(&NAME, String::from("regen")).with(&HEIGHT, 1);
+    let next = next_height(&ctx);
+    assert_eq!(*next.get(&HEIGHT).unwrap(), 2);
+    assert_eq!(next.get(&NAME).unwrap(), "regen");
+    // the original is unchanged
+    assert_eq!(*ctx.get(&HEIGHT).unwrap(), 1);
+
+    let next = HEIGHT.set(next.as_ref(), 3);
+    assert_eq!(*HEIGHT.get(next.as_ref()).unwrap(), 3);
+    let (name, next) = next.take(&NAME).unwrap();
+    assert_eq!(name, "regen");
+    assert!(matches!(next.get(&NAME), Err(ContextError::NotFound { .. })));
+    assert!(matches!(NAME.unset(next.as_ref()).get(&HEIGHT), Ok(3)));
+}
+
+#[test]
+fn test_keys_dont_collide() {
+    assert_eq!(HEIGHT.name(), "context::HEIGHT");
+    assert_eq!(other::HEIGHT.name(), "context::other::HEIGHT");
+    let ctx = SimpleContext::new().with(&HEIGHT, 1).with(&other::HEIGHT, 2);
+    assert_eq!(*ctx.get(&HEIGHT).unwrap(), 1);
+    assert_eq!(*ctx.get(&other::HEIGHT).unwrap(), 2);
+    assert_eq!(HEIGHT.id(), HEIGHT.id());
+    assert_ne!(HEIGHT.id(), other::HEIGHT.id());
+}
+
+mod abci {
+    regen_context::context_key!(pub CHAIN_ID: String, debug);
+}
+
+#[test]
+fn test_introspection() {
+    let ctx = SimpleContext::new().with(&HEIGHT, 7).with(&abci::CHAIN_ID, String::from("regen-1"));
+    let keys: Vec<(&str, &str)> = ctx.keys().iter().map(|k| (k.name(), k.type_name())).collect();
+    // type names aren't stable across compilers
+    let string = type_name::<String>();
+    assert_eq!(keys, vec![("context::HEIGHT", type_name::<u64>()), ("context::abci::CHAIN_ID", string)]);
+    assert_eq!(format!("{:?}", ctx), format!(r#"{{context::HEIGHT: {}: .., context::abci::CHAIN_ID: {}: "regen-1"}}"#, type_name::<u64>(), string));
+    let dyn_ctx: &dyn Context = &ctx;
+    assert_eq!(format!("{:?}", dyn_ctx), format!("{:?}", ctx));
+
+    let err = ctx.get(&NAME).unwrap_err();
+    assert_eq!(err.to_string(), "key context::NAME not found");
+}
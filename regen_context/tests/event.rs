@@ -0,0 +1,64 @@
+use regen_context::{Context, SimpleContext};
+use regen_context::event::{collect_events, emit, with_events, Event, EventManager, TypedEvent, EVENTS};
+
+struct Transfer {
+    from: String,
+    to: String,
+    amount: u64,
+}
+
+impl TypedEvent for Transfer {
+    fn to_event(&self) -> Event {
+        Event::new("transfer")
+            .indexed_attr("from", &self.from)
+            .indexed_attr("to", &self.to)
+            .attr("amount", &self.amount.to_string())
+    }
+}
+
+fn transfer(ctx: &dyn Context, amount: u64) -> Result<(), String> {
+    EVENTS.get(ctx).unwrap().emit_typed(&Transfer { from: String::from("alice"), to: String::from("bob"), amount });
+    if amount > 100 {
+        return Err(String::from("insufficient funds"));
+    }
+    Ok(())
+}
+
+#[test]
+fn test_events() {
+    let ctx = SimpleContext::new().with(&EVENTS, EventManager::new());
+    emit(&ctx, Event::new("begin")).unwrap();
+    with_events(&ctx, |ctx| transfer(ctx, 10)).unwrap();
+    // failed sub-executions discard their events, including those of nested ones
+    with_events(&ctx, |ctx| {
+        with_events(ctx, |ctx| transfer(ctx, 20))?;
+        transfer(ctx, 200)
+    }).unwrap_err();
+    with_events(&ctx, |ctx| with_events(ctx, |ctx| transfer(ctx, 30))).unwrap();
+
+    let events = ctx.get(&EVENTS).unwrap().take();
+    let types: Vec<&str> = events.iter().map(|e| e.event_type.as_str()).collect();
+    assert_eq!(types, vec!["begin", "transfer", "transfer"]);
+    assert_eq!(events[1].attributes[2].value, "10");
+    assert!(events[1].attributes[0].index);
+    assert!(!events[1].attributes[2].index);
+    assert_eq!(events[2].attributes[2].value, "30");
+    assert!(ctx.get(&EVENTS).unwrap().take().is_empty());
+
+    assert!(emit(&SimpleContext::new(), Event::new("lost")).is_err());
+}
+
+#[test]
+fn test_collect_events() {
+    let ctx = SimpleContext::new();
+    let (res, events) = collect_events(&ctx, |ctx| transfer(ctx, 10), |res| res.is_ok());
+    assert!(res.is_ok());
+    assert_eq!(events[0].kv_pairs(), vec![
+        (b"from".to_vec(), b"alice".to_vec()),
+        (b"to".to_vec(), b"bob".to_vec()),
+        (b"amount".to_vec(), b"10".to_vec()),
+    ]);
+    let (res, events) = collect_events(&ctx, |ctx| transfer(ctx, 200), |res| res.is_ok());
+    assert!(res.is_err());
+    assert!(events.is_empty());
+}
@@ -0,0 +1,149 @@
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use crate::{Map, OrderedMap, MutableMap, MutableOrderedMap, Batch, Iterator, Result};
+use crate::mem::btree_range;
+
+/// A generic batch over any parent `Batch`. Pending writes are kept in memory, reads see them
+/// before falling through to the parent.
+pub struct CacheBatch<'a, K, V> {
+    parent: &'a mut dyn Batch<K, V>,
+    ops: BTreeMap<K, Option<V>>,
+}
+
+impl<'a, K: Ord, V> CacheBatch<'a, K, V> {
+    pub fn new(parent: &'a mut dyn Batch<K, V>) -> Self {
+        CacheBatch { parent, ops: BTreeMap::new() }
+    }
+}
+
+impl<'a, K: Ord + Clone, V: Clone> Map<K, V> for CacheBatch<'a, K, V> {
+    fn get(&self, key: &K) -> Result<Option<V>> {
+        match self.ops.get(key) {
+            Some(op) => Ok(op.clone()),
+            None => self.parent.get(key),
+        }
+    }
+
+    fn has(&self, key: &K) -> Result<bool> {
+        match self.ops.get(key) {
+            Some(op) => Ok(op.is_some()),
+            None => self.parent.has(key),
+        }
+    }
+}
+
+impl<'a, K: Ord + Clone, V: Clone> OrderedMap<K, V> for CacheBatch<'a, K, V> {
+    fn iterator(&self, start: Option<&K>, end: Option<&K>) -> Result<Box<dyn Iterator<K, V> + '_>> {
+        Ok(Box::new(MergeIterator::new(self.parent.iterator(start, end)?, &self.ops, start, end, false)))
+    }
+
+    fn reverse_iterator(&self, start: Option<&K>, end: Option<&K>) -> Result<Box<dyn Iterator<K, V> + '_>> {
+        Ok(Box::new(MergeIterator::new(self.parent.reverse_iterator(start, end)?, &self.ops, start, end, true)))
+    }
+
+    // the parent's count adjusted by the pending writes, so batches over stores with a fast
+    // count stay fast
+    fn count(&self, start: Option<&K>, end: Option<&K>) -> Result<u64> {
+        let mut n = self.parent.count(start, end)?;
+        for (k, op) in btree_range(&self.ops, start, end) {
+            match (self.parent.has(k)?, op.is_some()) {
+                (false, true) => n += 1,
+                (true, false) => n -= 1,
+                _ => {}
+            }
+        }
+        Ok(n)
+    }
+}
+
+impl<'a, K: Ord + Clone, V: Clone> MutableMap<K, V> for CacheBatch<'a, K, V> {
+    fn set(&mut self, key: &K, value: &V) -> Result<()> {
+        self.ops.insert(key.clone(), Some(value.clone()));
+        Ok(())
+    }
+
+    fn delete(&mut self, key: &K) -> Result<()> {
+        self.ops.insert(key.clone(), None);
+        Ok(())
+    }
+}
+
+impl<'a, K: Ord + Clone, V: Clone> MutableOrderedMap<K, V> for CacheBatch<'a, K, V> {}
+
+impl<'a, K: Ord + Clone, V: Clone> Batch<K, V> for CacheBatch<'a, K, V> {
+    fn new_batch(&mut self) -> Box<dyn Batch<K, V> + '_> {
+        Box::new(CacheBatch::new(self))
+    }
+
+    fn write(&mut self) -> Result<()> {
+        let ops = std::mem::take(&mut self.ops);
+        for (k, op) in ops.iter() {
+            match op {
+                Some(v) => self.parent.set(k, v)?,
+                None => self.parent.delete(k)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+type OpIterator<'a, K, V> = std::iter::Peekable<Box<dyn std::iter::Iterator<Item = (&'a K, &'a Option<V>)> + 'a>>;
+
+/// Merges a parent iterator with pending writes, the pending writes winning on equal keys.
+pub struct MergeIterator<'a, K, V> {
+    parent: Box<dyn Iterator<K, V> + 'a>,
+    parent_next: Option<(K, V)>,
+    parent_done: bool,
+    ops: OpIterator<'a, K, V>,
+    reverse: bool,
+}
+
+impl<'a, K: Ord + Clone, V> MergeIterator<'a, K, V> {
+    pub fn new(parent: Box<dyn Iterator<K, V> + 'a>, ops: &'a BTreeMap<K, Option<V>>, start: Option<&K>, end: Option<&K>, reverse: bool) -> Self {
+        let ops = btree_range(ops, start, end);
+        MergeIterator {
+            parent,
+            parent_next: None,
+            parent_done: false,
+            ops: if reverse {
+                (Box::new(ops.rev()) as Box<dyn std::iter::Iterator<Item = _>>).peekable()
+            } else {
+                (Box::new(ops) as Box<dyn std::iter::Iterator<Item = _>>).peekable()
+            },
+            reverse,
+        }
+    }
+}
+
+impl<'a, K: Ord + Clone, V: Clone> Iterator<K, V> for MergeIterator<'a, K, V> {
+    fn next(&mut self) -> Result<Option<(K, V)>> {
+        loop {
+            if self.parent_next.is_none() && !self.parent_done {
+                self.parent_next = self.parent.next()?;
+                self.parent_done = self.parent_next.is_none();
+            }
+            let op_key = self.ops.peek().map(|(k, _)| *k);
+            let take_op = match (&self.parent_next, op_key) {
+                (None, None) => return Ok(None),
+                (None, Some(_)) => true,
+                (Some(_), None) => false,
+                (Some((pk, _)), Some(ok)) => {
+                    let ord = ok.cmp(pk);
+                    let ord = if self.reverse { ord.reverse() } else { ord };
+                    if ord == Ordering::Equal {
+                        // the pending write shadows the parent's entry
+                        self.parent_next = None;
+                    }
+                    ord != Ordering::Greater
+                }
+            };
+            if !take_op {
+                return Ok(self.parent_next.take());
+            }
+            let (k, op) = self.ops.next().unwrap();
+            if let Some(v) = op {
+                return Ok(Some((k.clone(), v.clone())));
+            }
+        }
+    }
+}
@@ -1,4 +1,11 @@
-use err_derive;
+// the impls generated by err_derive's `Error` derive are wrapped in a const
+#![allow(non_local_definitions)]
+
+use std::ops::{Bound, Range};
+
+pub mod mem;
+pub mod cache;
+pub mod multi;
 
 #[derive(Debug, err_derive::Error)]
 pub enum StoreError {
@@ -15,9 +22,19 @@ pub trait Entry<K, V> {
     fn value(&self) -> &V;
 }
 
+impl<K, V> Entry<K, V> for (K, V) {
+    fn key(&self) -> &K {
+        &self.0
+    }
+
+    fn value(&self) -> &V {
+        &self.1
+    }
+}
+
 pub trait Iterator<K, V> {
-    fn next(&self) -> Result<&dyn Entry<K, V>>;
-    fn release(&self);
+    fn next(&mut self) -> Result<Option<(K, V)>>;
+    fn release(&self) {}
 }
 
 pub trait Map<K, V> {
@@ -25,9 +42,20 @@ pub trait Map<K, V> {
     fn has(&self, key: &K) -> Result<bool>;
 }
 
+/// Iterators cover `start` inclusive to `end` exclusive, `None` meaning unbounded on that side.
 pub trait OrderedMap<K, V>: Map<K, V> {
-    fn iterator(&self, start: &K, end: &K) -> Result<Box<dyn Iterator<K, V>>>;
-    fn reverse_iterator(&self, start: &K, end: &K) -> Result<Box<dyn Iterator<K, V>>>;
+    fn iterator(&self, start: Option<&K>, end: Option<&K>) -> Result<Box<dyn Iterator<K, V> + '_>>;
+    fn reverse_iterator(&self, start: Option<&K>, end: Option<&K>) -> Result<Box<dyn Iterator<K, V> + '_>>;
+
+    /// Counts the entries in the range. This scans the range unless the store can do better.
+    fn count(&self, start: Option<&K>, end: Option<&K>) -> Result<u64> {
+        let mut it = self.iterator(start, end)?;
+        let mut n = 0;
+        while it.next()?.is_some() {
+            n += 1;
+        }
+        Ok(n)
+    }
 }
 
 pub trait MutableMap<K, V>: Map<K, V> {
@@ -43,13 +71,50 @@ pub trait PersistentMap<K, V> {
     fn without(&self, key: &K) -> Result<Box<dyn PersistentMap<K, V>>>;
 }
 
-pub trait Batch<'a, K, V>: MutableOrderedMap<K, V> {
-    fn new_batch(&'a mut self) -> &mut dyn Batch<'a, K, V>;
+/// A batch buffers writes on top of its parent. `write` flushes them to the parent,
+/// dropping a batch without writing discards them.
+pub trait Batch<K, V>: MutableOrderedMap<K, V> {
+    fn new_batch(&mut self) -> Box<dyn Batch<K, V> + '_>;
     fn write(&mut self) -> Result<()>;
 }
 
-pub trait CommitKVStore<'a, K, V, Commit>: Batch<'a, K, V> {
+pub trait CommitKVStore<K, V, Commit>: Batch<K, V> {
     fn commit(&mut self) -> Result<Commit>;
 }
 
+/// Read access to the state as of each committed version.
+pub trait VersionedMap<K, V> {
+    fn latest_version(&self) -> u64;
+    fn get_at(&self, version: u64, key: &K) -> Result<Option<V>>;
+    fn has_at(&self, version: u64, key: &K) -> Result<bool>;
+}
+
+pub trait VersionedOrderedMap<K, V>: VersionedMap<K, V> {
+    fn iterator_at(&self, version: u64, start: Option<&K>, end: Option<&K>) -> Result<Box<dyn Iterator<K, V> + '_>>;
+    fn reverse_iterator_at(&self, version: u64, start: Option<&K>, end: Option<&K>) -> Result<Box<dyn Iterator<K, V> + '_>>;
+}
+
+pub trait PrunableVersionedMap<K, V>: VersionedMap<K, V> {
+    /// Deletes the versions in `versions`. The latest version can't be deleted.
+    fn delete_versions(&mut self, versions: Range<u64>) -> Result<()>;
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CommitID {
+    pub version: u64,
+    pub hash: Vec<u8>,
+}
+
+pub fn range_bounds<K: Clone>(start: Option<&K>, end: Option<&K>) -> (Bound<K>, Bound<K>) {
+    (
+        start.map_or(Bound::Unbounded, |s| Bound::Included(s.clone())),
+        end.map_or(Bound::Unbounded, |e| Bound::Excluded(e.clone())),
+    )
+}
 
+pub fn is_empty_range<K: Ord>(start: Option<&K>, end: Option<&K>) -> bool {
+    match (start, end) {
+        (Some(s), Some(e)) => s >= e,
+        _ => false,
+    }
+}
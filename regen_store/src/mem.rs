@@ -0,0 +1,115 @@
+use std::collections::BTreeMap;
+use std::collections::btree_map::Range;
+use blake2::{Blake2b, Digest};
+use crate::{Map, OrderedMap, MutableMap, MutableOrderedMap, Batch, CommitKVStore, CommitID, Iterator, Result, range_bounds, is_empty_range};
+use crate::cache::CacheBatch;
+
+/// An in-memory store, mostly useful for tests and for state that doesn't need to survive a restart.
+#[derive(Default, Clone)]
+pub struct MemStore {
+    data: BTreeMap<Vec<u8>, Vec<u8>>,
+    version: u64,
+}
+
+impl MemStore {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn version(&self) -> u64 {
+        self.version
+    }
+
+    fn hash(&self) -> Vec<u8> {
+        let mut hasher = Blake2b::new();
+        for (k, v) in self.data.iter() {
+            hasher.input((k.len() as u64).to_be_bytes());
+            hasher.input(k);
+            hasher.input((v.len() as u64).to_be_bytes());
+            hasher.input(v);
+        }
+        hasher.result().to_vec()
+    }
+}
+
+pub(crate) fn btree_range<'a, K: Ord + Clone, V>(map: &'a BTreeMap<K, V>, start: Option<&K>, end: Option<&K>) -> Range<'a, K, V> {
+    if is_empty_range(start, end) {
+        // BTreeMap::range panics on an inverted range
+        return map.range(range_bounds(start, start));
+    }
+    map.range(range_bounds(start, end))
+}
+
+pub(crate) struct RangeIterator<'a, K, V> {
+    range: Range<'a, K, V>,
+    reverse: bool,
+}
+
+impl<'a, K, V> RangeIterator<'a, K, V> {
+    pub(crate) fn new(range: Range<'a, K, V>, reverse: bool) -> Self {
+        RangeIterator { range, reverse }
+    }
+}
+
+impl<'a, K: Clone, V: Clone> Iterator<K, V> for RangeIterator<'a, K, V> {
+    fn next(&mut self) -> Result<Option<(K, V)>> {
+        let next = if self.reverse {
+            self.range.next_back()
+        } else {
+            self.range.next()
+        };
+        Ok(next.map(|(k, v)| (k.clone(), v.clone())))
+    }
+}
+
+impl Map<Vec<u8>, Vec<u8>> for MemStore {
+    fn get(&self, key: &Vec<u8>) -> Result<Option<Vec<u8>>> {
+        Ok(self.data.get(key).cloned())
+    }
+
+    fn has(&self, key: &Vec<u8>) -> Result<bool> {
+        Ok(self.data.contains_key(key))
+    }
+}
+
+impl OrderedMap<Vec<u8>, Vec<u8>> for MemStore {
+    fn iterator(&self, start: Option<&Vec<u8>>, end: Option<&Vec<u8>>) -> Result<Box<dyn Iterator<Vec<u8>, Vec<u8>> + '_>> {
+        Ok(Box::new(RangeIterator::new(btree_range(&self.data, start, end), false)))
+    }
+
+    fn reverse_iterator(&self, start: Option<&Vec<u8>>, end: Option<&Vec<u8>>) -> Result<Box<dyn Iterator<Vec<u8>, Vec<u8>> + '_>> {
+        Ok(Box::new(RangeIterator::new(btree_range(&self.data, start, end), true)))
+    }
+}
+
+impl MutableMap<Vec<u8>, Vec<u8>> for MemStore {
+    fn set(&mut self, key: &Vec<u8>, value: &Vec<u8>) -> Result<()> {
+        self.data.insert(key.clone(), value.clone());
+        Ok(())
+    }
+
+    fn delete(&mut self, key: &Vec<u8>) -> Result<()> {
+        self.data.remove(key);
+        Ok(())
+    }
+}
+
+impl MutableOrderedMap<Vec<u8>, Vec<u8>> for MemStore {}
+
+impl Batch<Vec<u8>, Vec<u8>> for MemStore {
+    fn new_batch(&mut self) -> Box<dyn Batch<Vec<u8>, Vec<u8>> + '_> {
+        Box::new(CacheBatch::new(self))
+    }
+
+    fn write(&mut self) -> Result<()> {
+        // writes go straight to memory
+        Ok(())
+    }
+}
+
+impl CommitKVStore<Vec<u8>, Vec<u8>, CommitID> for MemStore {
+    fn commit(&mut self) -> Result<CommitID> {
+        self.version += 1;
+        Ok(CommitID { version: self.version, hash: self.hash() })
+    }
+}
@@ -0,0 +1,190 @@
+use std::collections::BTreeMap;
+use blake2::{Blake2b, Digest};
+use crate::{CommitKVStore, CommitID, Result, StoreError};
+
+pub type KVStore = dyn CommitKVStore<Vec<u8>, Vec<u8>, CommitID>;
+
+/// Mounts independently committable stores under names and commits them together. The app hash
+/// is the root of a binary Merkle tree over `(name, store hash)` leaves ordered by name.
+#[derive(Default)]
+pub struct MultiStore {
+    stores: BTreeMap<String, Box<KVStore>>,
+    commits: BTreeMap<String, CommitID>,
+    last_commit: CommitID,
+}
+
+/// Links the hash of a single mounted store to an app hash.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StoreProof {
+    pub name: String,
+    pub store_hash: Vec<u8>,
+    pub index: usize,
+    pub total: usize,
+    pub aunts: Vec<Vec<u8>>,
+}
+
+impl MultiStore {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn mount(&mut self, name: &str, store: Box<KVStore>) -> Result<()> {
+        if self.stores.contains_key(name) {
+            return Err(Box::from(StoreError::Other(format!("store {} is already mounted", name))));
+        }
+        self.stores.insert(String::from(name), store);
+        Ok(())
+    }
+
+    pub fn store(&self, name: &str) -> Option<&KVStore> {
+        self.stores.get(name).map(|s| s.as_ref())
+    }
+
+    pub fn store_mut(&mut self, name: &str) -> Option<&mut KVStore> {
+        self.stores.get_mut(name).map(|s| s.as_mut())
+    }
+
+    pub fn store_names(&self) -> Vec<&str> {
+        self.stores.keys().map(|k| k.as_str()).collect()
+    }
+
+    pub fn last_commit(&self) -> &CommitID {
+        &self.last_commit
+    }
+
+    pub fn store_commit(&self, name: &str) -> Option<&CommitID> {
+        self.commits.get(name)
+    }
+
+    /// Writes and commits every mounted store, returning the new app hash. Every store is
+    /// written before any is committed, so a failed write leaves them all uncommitted.
+    ///
+    /// Commits can't be undone though. If a store fails to commit after others did, those show
+    /// their new commit in `store_commit`, `last_commit` is unchanged and the error names the
+    /// failed store. The stores are then at different versions and the app has to stop.
+    pub fn commit(&mut self) -> Result<CommitID> {
+        for (name, store) in self.stores.iter_mut() {
+            if let Err(e) = store.write() {
+                return Err(Box::from(StoreError::Other(format!("writing store {}: {}", name, e))));
+            }
+        }
+        for (name, store) in self.stores.iter_mut() {
+            match store.commit() {
+                Err(e) => return Err(Box::from(StoreError::Other(format!("committing store {}: {}", name, e)))),
+                Ok(commit) => self.commits.insert(name.clone(), commit),
+            };
+        }
+        self.last_commit = CommitID {
+            version: self.last_commit.version + 1,
+            hash: merkle_root(&self.leaves()),
+        };
+        Ok(self.last_commit.clone())
+    }
+
+    /// Proves the hash of the named store as of the last commit.
+    pub fn prove(&self, name: &str) -> Result<StoreProof> {
+        let index = match self.commits.keys().position(|k| k == name) {
+            None => return Err(Box::from(StoreError::Other(format!("store {} has no commit", name)))),
+            Some(i) => i,
+        };
+        let leaves = self.leaves();
+        Ok(StoreProof {
+            name: String::from(name),
+            store_hash: self.commits[name].hash.clone(),
+            index,
+            total: leaves.len(),
+            aunts: merkle_aunts(&leaves, index),
+        })
+    }
+
+    fn leaves(&self) -> Vec<Vec<u8>> {
+        self.commits.iter().map(|(name, commit)| leaf_hash(name, &commit.hash)).collect()
+    }
+}
+
+impl StoreProof {
+    pub fn root_hash(&self) -> Option<Vec<u8>> {
+        if self.index >= self.total {
+            return None;
+        }
+        root_from_aunts(self.index, self.total, leaf_hash(&self.name, &self.store_hash), &self.aunts)
+    }
+
+    pub fn verify(&self, app_hash: &[u8]) -> bool {
+        match self.root_hash() {
+            None => false,
+            Some(root) => root.as_slice() == app_hash,
+        }
+    }
+}
+
+fn leaf_hash(name: &str, hash: &[u8]) -> Vec<u8> {
+    let mut hasher = Blake2b::new();
+    hasher.input([0]);
+    hasher.input((name.len() as u64).to_be_bytes());
+    hasher.input(name.as_bytes());
+    hasher.input((hash.len() as u64).to_be_bytes());
+    hasher.input(hash);
+    hasher.result().to_vec()
+}
+
+fn inner_hash(left: &[u8], right: &[u8]) -> Vec<u8> {
+    let mut hasher = Blake2b::new();
+    hasher.input([1]);
+    hasher.input(left);
+    hasher.input(right);
+    hasher.result().to_vec()
+}
+
+// largest power of two strictly less than n
+fn split_point(n: usize) -> usize {
+    let mut k = 1;
+    while k * 2 < n {
+        k *= 2;
+    }
+    k
+}
+
+fn merkle_root(leaves: &[Vec<u8>]) -> Vec<u8> {
+    match leaves.len() {
+        0 => Vec::new(),
+        1 => leaves[0].clone(),
+        n => {
+            let k = split_point(n);
+            inner_hash(&merkle_root(&leaves[..k]), &merkle_root(&leaves[k..]))
+        }
+    }
+}
+
+// aunts are ordered from the leaf up to the root
+fn merkle_aunts(leaves: &[Vec<u8>], index: usize) -> Vec<Vec<u8>> {
+    let n = leaves.len();
+    if n <= 1 {
+        return Vec::new();
+    }
+    let k = split_point(n);
+    if index < k {
+        let mut aunts = merkle_aunts(&leaves[..k], index);
+        aunts.push(merkle_root(&leaves[k..]));
+        aunts
+    } else {
+        let mut aunts = merkle_aunts(&leaves[k..], index - k);
+        aunts.push(merkle_root(&leaves[..k]));
+        aunts
+    }
+}
+
+fn root_from_aunts(index: usize, total: usize, leaf: Vec<u8>, aunts: &[Vec<u8>]) -> Option<Vec<u8>> {
+    if total == 1 {
+        return if aunts.is_empty() { Some(leaf) } else { None };
+    }
+    let (last, rest) = aunts.split_last()?;
+    let k = split_point(total);
+    if index < k {
+        let left = root_from_aunts(index, k, leaf, rest)?;
+        Some(inner_hash(&left, last))
+    } else {
+        let right = root_from_aunts(index - k, total - k, leaf, rest)?;
+        Some(inner_hash(last, &right))
+    }
+}
@@ -0,0 +1,4 @@
+use regen_store::mem::MemStore;
+use regen_store_test::store_conformance_tests;
+
+store_conformance_tests!(MemStore::new);
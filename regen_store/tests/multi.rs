@@ -0,0 +1,151 @@
+use regen_store::{Map, OrderedMap, MutableMap, MutableOrderedMap, Batch, CommitKVStore, CommitID, Iterator, Result, StoreError};
+use regen_store::mem::MemStore;
+use regen_store::multi::MultiStore;
+
+fn new_multi_store() -> MultiStore {
+    let mut ms = MultiStore::new();
+    for name in ["auth", "bank", "data"].iter() {
+        ms.mount(name, Box::new(MemStore::new())).unwrap();
+    }
+    ms
+}
+
+#[test]
+fn test_mount_twice() {
+    let mut ms = new_multi_store();
+    assert!(ms.mount("bank", Box::new(MemStore::new())).is_err());
+}
+
+#[test]
+fn test_commit_app_hash() {
+    let mut ms1 = new_multi_store();
+    let mut ms2 = new_multi_store();
+    ms1.store_mut("bank").unwrap().set(&b"foo".to_vec(), &b"bar".to_vec()).unwrap();
+    ms2.store_mut("bank").unwrap().set(&b"foo".to_vec(), &b"bar".to_vec()).unwrap();
+    let c1 = ms1.commit().unwrap();
+    let c2 = ms2.commit().unwrap();
+    assert_eq!(c1, c2);
+    assert_eq!(c1.version, 1);
+
+    ms1.store_mut("data").unwrap().set(&b"foo".to_vec(), &b"baz".to_vec()).unwrap();
+    let c3 = ms1.commit().unwrap();
+    assert_eq!(c3.version, 2);
+    assert_ne!(c1.hash, c3.hash);
+}
+
+#[test]
+fn test_store_proofs() {
+    let mut ms = new_multi_store();
+    ms.mount("gov", Box::new(MemStore::new())).unwrap();
+    ms.mount("sig", Box::new(MemStore::new())).unwrap();
+    ms.store_mut("auth").unwrap().set(&b"a".to_vec(), &b"1".to_vec()).unwrap();
+    let commit = ms.commit().unwrap();
+    for name in ms.store_names() {
+        let proof = ms.prove(name).unwrap();
+        assert_eq!(&proof.store_hash, &ms.store_commit(name).unwrap().hash);
+        assert!(proof.verify(&commit.hash));
+
+        let mut bad = proof.clone();
+        bad.store_hash = b"bad".to_vec();
+        assert!(!bad.verify(&commit.hash));
+    }
+    assert!(ms.prove("missing").is_err());
+}
+
+#[test]
+fn test_discarded_batch() {
+    let mut ms = new_multi_store();
+    let store = ms.store_mut("auth").unwrap();
+    {
+        let mut batch = store.new_batch();
+        batch.set(&b"a".to_vec(), &b"1".to_vec()).unwrap();
+        assert!(batch.has(&b"a".to_vec()).unwrap());
+    }
+    assert!(!store.has(&b"a".to_vec()).unwrap());
+    {
+        let mut batch = store.new_batch();
+        batch.set(&b"a".to_vec(), &b"1".to_vec()).unwrap();
+        batch.write().unwrap();
+    }
+    assert_eq!(store.get(&b"a".to_vec()).unwrap(), Some(b"1".to_vec()));
+}
+
+// fails to write or to commit
+struct FailingStore(MemStore, &'static str);
+
+impl FailingStore {
+    fn fail(&self, op: &str) -> Result<()> {
+        if self.1 == op {
+            return Err(Box::from(StoreError::Other(format!("{} failed", op))));
+        }
+        Ok(())
+    }
+}
+
+impl Map<Vec<u8>, Vec<u8>> for FailingStore {
+    fn get(&self, key: &Vec<u8>) -> Result<Option<Vec<u8>>> {
+        self.0.get(key)
+    }
+
+    fn has(&self, key: &Vec<u8>) -> Result<bool> {
+        self.0.has(key)
+    }
+}
+
+impl OrderedMap<Vec<u8>, Vec<u8>> for FailingStore {
+    fn iterator(&self, start: Option<&Vec<u8>>, end: Option<&Vec<u8>>) -> Result<Box<dyn Iterator<Vec<u8>, Vec<u8>> + '_>> {
+        self.0.iterator(start, end)
+    }
+
+    fn reverse_iterator(&self, start: Option<&Vec<u8>>, end: Option<&Vec<u8>>) -> Result<Box<dyn Iterator<Vec<u8>, Vec<u8>> + '_>> {
+        self.0.reverse_iterator(start, end)
+    }
+}
+
+impl MutableMap<Vec<u8>, Vec<u8>> for FailingStore {
+    fn set(&mut self, key: &Vec<u8>, value: &Vec<u8>) -> Result<()> {
+        self.0.set(key, value)
+    }
+
+    fn delete(&mut self, key: &Vec<u8>) -> Result<()> {
+        self.0.delete(key)
+    }
+}
+
+impl MutableOrderedMap<Vec<u8>, Vec<u8>> for FailingStore {}
+
+impl Batch<Vec<u8>, Vec<u8>> for FailingStore {
+    fn new_batch(&mut self) -> Box<dyn Batch<Vec<u8>, Vec<u8>> + '_> {
+        self.0.new_batch()
+    }
+
+    fn write(&mut self) -> Result<()> {
+        self.fail("write")
+    }
+}
+
+impl CommitKVStore<Vec<u8>, Vec<u8>, CommitID> for FailingStore {
+    fn commit(&mut self) -> Result<CommitID> {
+        self.fail("commit")?;
+        self.0.commit()
+    }
+}
+
+#[test]
+fn test_failed_commit() {
+    // a failed write commits nothing
+    let mut ms = new_multi_store();
+    ms.mount("zzz", Box::new(FailingStore(MemStore::new(), "write"))).unwrap();
+    assert!(ms.commit().is_err());
+    assert_eq!(ms.store_commit("auth"), None);
+    assert_eq!(ms.last_commit().version, 0);
+
+    // a failed commit is reported, along with the stores which committed
+    let mut ms = new_multi_store();
+    ms.mount("zzz", Box::new(FailingStore(MemStore::new(), "commit"))).unwrap();
+    let err = ms.commit().unwrap_err().to_string();
+    assert!(err.contains("zzz"), "{}", err);
+    assert_eq!(ms.store_commit("auth").unwrap().version, 1);
+    assert_eq!(ms.store_commit("zzz"), None);
+    assert_eq!(ms.last_commit().version, 0);
+}
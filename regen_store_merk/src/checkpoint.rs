@@ -0,0 +1,258 @@
+use std::convert::TryInto;
+use std::path::{Path, PathBuf};
+use merk::{Hash, Merk};
+use merk::rocksdb::{ColumnFamily, ColumnFamilyDescriptor, WriteBatch, DB};
+use merk::tree::{kv_hash, Fetch, Link, RefWalker, Tree, NULL_HASH};
+use regen_store::{CommitID, Result, StoreError};
+use crate::{MerkStore, VERSION_KEY, merk_err};
+use crate::proof::root_hash;
+
+// Restoring writes the nodes in the RocksDB layout of merk 1.0.1, which merk doesn't expose:
+// the nodes in the default column family keyed by their key, aux data in the "aux" column
+// family and the key of the root node under ROOT_KEY in the "internal" one. The layout has to
+// be checked before upgrading merk, which is why its version is pinned.
+const ROOT_KEY: &[u8] = b"root";
+
+const CHUNK_NODES: usize = 1000;
+
+const HAS_LEFT: u8 = 1;
+const HAS_RIGHT: u8 = 2;
+
+/// A point-in-time copy of a `MerkStore` as of its last commit, which chunks for state sync are
+/// produced from while the store keeps committing.
+pub struct Checkpoint {
+    store: MerkStore,
+}
+
+impl MerkStore {
+    /// Copies the tree of the last commit to a new Merk database at `path`, which must not
+    /// exist yet. Uncommitted writes aren't included.
+    pub fn checkpoint<P: AsRef<Path>>(&self, path: P) -> Result<Checkpoint> {
+        let mut restorer = MerkRestorer::new(path, self.last_commit.clone())?;
+        let mut chunks = Chunks { merk: &self.merk, last: None, done: false };
+        while let Some(chunk) = chunks.next_chunk()? {
+            restorer.process_chunk(&chunk)?;
+        }
+        Ok(Checkpoint { store: restorer.finalize()? })
+    }
+}
+
+impl Checkpoint {
+    pub fn commit(&self) -> &CommitID {
+        self.store.last_commit()
+    }
+
+    pub fn chunks(&self) -> Chunks<'_> {
+        Chunks { merk: &self.store.merk, last: None, done: false }
+    }
+
+    /// Deletes the checkpoint from disk.
+    pub fn destroy(self) -> Result<()> {
+        self.store.merk.destroy().map_err(merk_err)
+    }
+}
+
+/// The nodes of a Merk tree in post-order, children before their parent, with up to
+/// `CHUNK_NODES` nodes per chunk. A node is encoded as a byte of `HAS_LEFT` and `HAS_RIGHT`
+/// flags followed by its key and value, each prefixed by its big-endian `u32` length.
+pub struct Chunks<'a> {
+    merk: &'a Merk,
+    last: Option<Vec<u8>>,
+    done: bool,
+}
+
+struct Node {
+    key: Vec<u8>,
+    value: Vec<u8>,
+    flags: u8,
+}
+
+impl Node {
+    fn of(tree: &Tree) -> Self {
+        let mut flags = 0;
+        if tree.link(true).is_some() {
+            flags |= HAS_LEFT;
+        }
+        if tree.link(false).is_some() {
+            flags |= HAS_RIGHT;
+        }
+        Node { key: tree.key().to_vec(), value: tree.value().to_vec(), flags }
+    }
+
+    fn encode_into(&self, bz: &mut Vec<u8>) {
+        bz.push(self.flags);
+        for part in [&self.key, &self.value].iter() {
+            bz.extend_from_slice(&(part.len() as u32).to_be_bytes());
+            bz.extend_from_slice(part);
+        }
+    }
+}
+
+impl<'a> Chunks<'a> {
+    pub fn next_chunk(&mut self) -> Result<Option<Vec<u8>>> {
+        let mut chunk = Vec::new();
+        for _ in 0..CHUNK_NODES {
+            if self.done {
+                break;
+            }
+            let last = self.last.as_deref();
+            let node = self.merk.walk(|walker| match walker {
+                None => Ok(None),
+                Some(mut walker) => next_post_order(&mut walker, last),
+            })?;
+            match node {
+                None => self.done = true,
+                Some(node) => {
+                    node.encode_into(&mut chunk);
+                    self.last = Some(node.key);
+                }
+            }
+        }
+        Ok(if chunk.is_empty() { None } else { Some(chunk) })
+    }
+}
+
+// the node after `after` in the post-order of the subtree of `walker`, or its first node if
+// `after` is `None`
+fn next_post_order<S: Fetch + Clone + Send>(walker: &mut RefWalker<S>, after: Option<&[u8]>) -> Result<Option<Node>> {
+    let after = match after {
+        None => return first_post_order(walker).map(Some),
+        Some(after) => after,
+    };
+    if after == walker.tree().key() {
+        return Ok(None);
+    }
+    let left = after < walker.tree().key();
+    {
+        let mut child = match walker.walk(left).map_err(merk_err)? {
+            None => return Err(Box::from(StoreError::Other(String::from("merk tree changed while chunking")))),
+            Some(child) => child,
+        };
+        if let Some(node) = next_post_order(&mut child, Some(after))? {
+            return Ok(Some(node));
+        }
+    }
+    // `after` was the root of the subtree on the `left` side
+    if left {
+        if let Some(mut right) = walker.walk(false).map_err(merk_err)? {
+            return first_post_order(&mut right).map(Some);
+        }
+    }
+    Ok(Some(Node::of(walker.tree())))
+}
+
+fn first_post_order<S: Fetch + Clone + Send>(walker: &mut RefWalker<S>) -> Result<Node> {
+    for left in [true, false].iter() {
+        if let Some(mut child) = walker.walk(*left).map_err(merk_err)? {
+            return first_post_order(&mut child);
+        }
+    }
+    Ok(Node::of(walker.tree()))
+}
+
+struct Subtree {
+    key: Vec<u8>,
+    hash: Hash,
+    child_heights: (u8, u8),
+}
+
+/// Rebuilds a `MerkStore` from the chunks of a checkpoint. The hash of every node is computed
+/// from the restored keys and values as the chunks are processed, and `finalize` checks the
+/// resulting root hash against the commit. A failed restore leaves a partial database at its
+/// path, which should be deleted.
+pub struct MerkRestorer {
+    db: DB,
+    path: PathBuf,
+    commit: CommitID,
+    // the subtrees restored so far whose parent hasn't been
+    stack: Vec<Subtree>,
+}
+
+fn invalid_chunk() -> Box<dyn std::error::Error> {
+    Box::from(StoreError::Other(String::from("invalid merk chunk")))
+}
+
+fn read<'a>(bz: &mut &'a [u8], len: usize) -> Result<&'a [u8]> {
+    if bz.len() < len {
+        return Err(invalid_chunk());
+    }
+    let (head, tail) = bz.split_at(len);
+    *bz = tail;
+    Ok(head)
+}
+
+fn read_part<'a>(bz: &mut &'a [u8]) -> Result<&'a [u8]> {
+    let len = u32::from_be_bytes(read(bz, 4)?.try_into()?);
+    read(bz, len as usize)
+}
+
+fn column_family<'a>(db: &'a DB, name: &str) -> Result<&'a ColumnFamily> {
+    match db.cf_handle(name) {
+        None => Err(Box::from(StoreError::Other(format!("merk: missing column family {}", name)))),
+        Some(cf) => Ok(cf),
+    }
+}
+
+impl MerkRestorer {
+    /// Starts restoring `commit` into a new store at `path`, which must not exist yet.
+    pub fn new<P: AsRef<Path>>(path: P, commit: CommitID) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        if path.exists() {
+            return Err(Box::from(StoreError::Other(format!("{} already exists", path.display()))));
+        }
+        // the column families of merk
+        let cfs = vec![
+            ColumnFamilyDescriptor::new("aux", Merk::default_db_opts()),
+            ColumnFamilyDescriptor::new("internal", Merk::default_db_opts()),
+        ];
+        let db = DB::open_cf_descriptors(&Merk::default_db_opts(), &path, cfs).map_err(merk_err)?;
+        Ok(MerkRestorer { db, path, commit, stack: Vec::new() })
+    }
+
+    /// Processes the next chunk, chunks must be given in order.
+    pub fn process_chunk(&mut self, chunk: &[u8]) -> Result<()> {
+        let mut batch = WriteBatch::default();
+        let mut bz = chunk;
+        while !bz.is_empty() {
+            let flags = read(&mut bz, 1)?[0];
+            let key = read_part(&mut bz)?.to_vec();
+            let value = read_part(&mut bz)?.to_vec();
+            // merk keys are shorter than 256 bytes
+            if flags & !(HAS_LEFT | HAS_RIGHT) != 0 || key.len() > 255 {
+                return Err(invalid_chunk());
+            }
+            let right = if flags & HAS_RIGHT != 0 { Some(self.pop_link()?) } else { None };
+            let left = if flags & HAS_LEFT != 0 { Some(self.pop_link()?) } else { None };
+            let kv = kv_hash(&key, &value);
+            let tree = Tree::from_fields(key, value, kv, left, right);
+            batch.put(tree.key(), tree.encode());
+            self.stack.push(Subtree { key: tree.key().to_vec(), hash: tree.hash(), child_heights: tree.child_heights() });
+        }
+        self.db.write(batch).map_err(merk_err)
+    }
+
+    fn pop_link(&mut self) -> Result<Link> {
+        match self.stack.pop() {
+            None => Err(invalid_chunk()),
+            Some(Subtree { key, hash, child_heights }) => Ok(Link::Pruned { hash, child_heights, key }),
+        }
+    }
+
+    /// Opens the restored store once every chunk has been processed.
+    pub fn finalize(mut self) -> Result<MerkStore> {
+        let root = self.stack.pop();
+        let hash = root.as_ref().map_or(NULL_HASH, |root| root.hash);
+        if !self.stack.is_empty() || hash != root_hash(&self.commit.hash)? {
+            return Err(Box::from(StoreError::Other(String::from("restored merk doesn't match the commit hash"))));
+        }
+        let mut batch = WriteBatch::default();
+        if let Some(root) = root {
+            batch.put_cf(column_family(&self.db, "internal")?, ROOT_KEY, root.key);
+        }
+        batch.put_cf(column_family(&self.db, "aux")?, VERSION_KEY, self.commit.version.to_be_bytes());
+        self.db.write(batch).map_err(merk_err)?;
+        self.db.flush().map_err(merk_err)?;
+        drop(self.db);
+        MerkStore::open(self.path)
+    }
+}
@@ -1,146 +1,224 @@
-use regen_store::{ReadonlyKVStore, StoreError, Iterator, KVStore, Batch, CommitKVStore, Commit};
-use merk::{Merk, Op};
 use std::collections::BTreeMap;
-use std::sync::mpsc::channel;
-use std::marker::PhantomData;
+use std::convert::TryInto;
+use std::fmt::Display;
+use std::ops::Bound;
+use std::path::Path;
+use merk::{Merk, Op};
+use merk::tree::{Fetch, RefWalker};
+use tempfile::TempDir;
+use regen_store::{Map, OrderedMap, MutableMap, MutableOrderedMap, Batch, CommitKVStore, CommitID, Iterator, Result, StoreError, is_empty_range};
+use regen_store::cache::{CacheBatch, MergeIterator};
 
-struct MerkReadonlyKVStore(Merk);
+pub mod proof;
+pub mod checkpoint;
 
-impl ReadonlyKVStore for MerkReadonlyKVStore {
-    fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, StoreError> {
-        self.0.get(key)
-            .map_err(|e| StoreError::Wrap(Box::from(e)))
-    }
-
-    fn has(&self, key: &[u8]) -> Result<bool, StoreError> {
-        let res = self.get(key)?;
-        Ok(res.is_some())
-    }
+/// A batch over a `MerkStore` or another batch. Reads check the pending ops of every level
+/// before falling back to Merk, `write` flushes the ops into the parent and dropping the batch
+/// discards them, so nesting one per transaction gives rollback within a block.
+pub type MerkKVStoreBatch<'a> = CacheBatch<'a, Vec<u8>, Vec<u8>>;
 
-    fn iterator(&self, start: &[u8], end: &[u8]) -> Result<Box<dyn Iterator>, StoreError> {
-        Err(StoreError::Other(String::from("not implemented")))
-    }
-
-    fn reverse_iterator(&self, start: &[u8], end: &[u8]) -> Result<Box<dyn Iterator>, StoreError> {
-        Err(StoreError::Other(String::from("not implemented")))
-    }
-}
+const VERSION_KEY: &[u8] = b"version";
 
-struct MerkKVStoreBatch<'a> {
-    store: &'a mut dyn MerkKVStoreBatchWriter,
-    changes: BTreeMap<Vec<u8>, Op>
+/// A `CommitKVStore` on top of a Merk AVL tree. Writes are kept in memory until `commit`, which
+/// applies them to Merk together with the new version in a single atomic write.
+pub struct MerkStore {
+    merk: Merk,
+    pending: BTreeMap<Vec<u8>, Option<Vec<u8>>>,
+    last_commit: CommitID,
+    // dropped after `merk`, so that it is closed before the directory is deleted
+    _temp_dir: Option<TempDir>,
 }
 
-trait MerkKVStoreBatchWriter: ReadonlyKVStore {
-    fn write_changes(&mut self, changes: &BTreeMap<Vec<u8>, Op>);
+fn merk_err<E: Display>(e: E) -> Box<dyn std::error::Error> {
+    Box::from(StoreError::Other(format!("merk: {}", e)))
 }
 
-impl <'a> ReadonlyKVStore for MerkKVStoreBatch<'a> {
-    fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, StoreError> {
-        unimplemented!()
-    }
-
-    fn has(&self, key: &[u8]) -> Result<bool, StoreError> {
-        unimplemented!()
-    }
-
-    fn iterator(&self, start: &[u8], end: &[u8]) -> Result<Box<dyn Iterator>, StoreError> {
-        unimplemented!()
-    }
-
-    fn reverse_iterator(&self, start: &[u8], end: &[u8]) -> Result<Box<dyn Iterator>, StoreError> {
-        unimplemented!()
+impl MerkStore {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Self::from_merk(Merk::open(path).map_err(merk_err)?)
+    }
+
+    /// Opens a store in a temporary directory which is removed when the store is dropped.
+    pub fn open_temporary() -> Result<Self> {
+        let dir = tempfile::tempdir()?;
+        let mut store = Self::open(dir.path())?;
+        store._temp_dir = Some(dir);
+        Ok(store)
+    }
+
+    fn from_merk(merk: Merk) -> Result<Self> {
+        let version = match merk.get_aux(VERSION_KEY).map_err(merk_err)? {
+            None => 0,
+            Some(bz) => u64::from_be_bytes(bz.as_slice().try_into()?),
+        };
+        let hash = if version == 0 { Vec::new() } else { merk.root_hash().to_vec() };
+        Ok(MerkStore {
+            merk,
+            pending: BTreeMap::new(),
+            last_commit: CommitID { version, hash },
+            _temp_dir: None,
+        })
+    }
+
+    pub fn last_commit(&self) -> &CommitID {
+        &self.last_commit
+    }
+
+    pub fn batch(&mut self) -> MerkKVStoreBatch<'_> {
+        CacheBatch::new(self)
+    }
+
+    fn merk_iterator(&self, start: Option<&Vec<u8>>, end: Option<&Vec<u8>>, reverse: bool) -> MerkIterator<'_> {
+        let bound = match (reverse, start, end) {
+            (false, Some(start), _) => Bound::Included(start.clone()),
+            (true, _, Some(end)) => Bound::Excluded(end.clone()),
+            _ => Bound::Unbounded,
+        };
+        MerkIterator {
+            merk: &self.merk,
+            bound,
+            start: start.cloned(),
+            end: end.cloned(),
+            reverse,
+            done: is_empty_range(start, end),
+        }
     }
 }
 
-impl <'a> KVStore for MerkKVStoreBatch<'a> {
-    fn set(&mut self, key: &[u8], value: &[u8]) -> Result<(), StoreError> {
-        self.changes.insert(Vec::from(key), Op::Put(Vec::from(value)));
-        Ok(())
-    }
-
-    fn delete(&mut self, key: &[u8]) -> Result<(), StoreError> {
-        self.changes.insert(Vec::from(key), Op::Delete);
-        Ok(())
-    }
+/// Iterates over the committed entries of the Merk tree. Each step looks up the entry after
+/// `bound` from the root, so the iterator holds no borrow of the tree between steps.
+struct MerkIterator<'a> {
+    merk: &'a Merk,
+    bound: Bound<Vec<u8>>,
+    start: Option<Vec<u8>>,
+    end: Option<Vec<u8>>,
+    reverse: bool,
+    done: bool,
 }
 
-impl <'a> MerkKVStoreBatchWriter for MerkKVStoreBatch<'a> {
-    fn write_changes(&mut self, changes: &BTreeMap<Vec<u8>, Op>) {
-        for (k, v) in changes.iter() {
-            self.changes.insert(k.clone(), *v.clone());
+// the first entry after `bound` in the subtree of `walker`, or the last one before it if
+// `reverse`
+fn seek<S: Fetch + Clone + Send>(walker: &mut RefWalker<S>, bound: &Bound<Vec<u8>>, reverse: bool) -> merk::Result<Option<(Vec<u8>, Vec<u8>)>> {
+    let key = walker.tree().key();
+    let in_bound = match bound {
+        Bound::Unbounded => true,
+        Bound::Included(b) => if reverse { key <= b.as_slice() } else { key >= b.as_slice() },
+        Bound::Excluded(b) => if reverse { key < b.as_slice() } else { key > b.as_slice() },
+    };
+    if !in_bound {
+        return match walker.walk(reverse)? {
+            None => Ok(None),
+            Some(mut child) => seek(&mut child, bound, reverse),
+        };
+    }
+    let entry = (key.to_vec(), walker.tree().value().to_vec());
+    if let Some(mut child) = walker.walk(!reverse)? {
+        if let Some(nearer) = seek(&mut child, bound, reverse)? {
+            return Ok(Some(nearer));
         }
-        unimplemented!()
     }
+    Ok(Some(entry))
 }
 
-impl <'a> Batch<'a> for MerkKVStoreBatch<'a> {
-    fn new_batch(&'a mut self) -> &mut dyn Batch<'a> {
-//        &mut MerkKVStoreBatch {
-//            store: self,
-//            changes: Default::default()
-//        }
-        unimplemented!()
-    }
-
-    fn write(&mut self) -> Result<(), StoreError> {
-        self.store.write_changes(&self.changes);
-        self.changes.clear();
-        Ok(())
+impl<'a> Iterator<Vec<u8>, Vec<u8>> for MerkIterator<'a> {
+    fn next(&mut self) -> Result<Option<(Vec<u8>, Vec<u8>)>> {
+        if self.done {
+            return Ok(None);
+        }
+        let (bound, reverse) = (&self.bound, self.reverse);
+        let entry = self.merk.walk(|walker| match walker {
+            None => Ok(None),
+            Some(mut walker) => seek(&mut walker, bound, reverse),
+        }).map_err(merk_err)?;
+        let (key, value) = match entry {
+            None => {
+                self.done = true;
+                return Ok(None);
+            }
+            Some(entry) => entry,
+        };
+        let out_of_range = if self.reverse {
+            self.start.as_ref().map(|start| &key < start)
+        } else {
+            self.end.as_ref().map(|end| &key >= end)
+        };
+        if let Some(true) = out_of_range {
+            self.done = true;
+            return Ok(None);
+        }
+        self.bound = Bound::Excluded(key.clone());
+        Ok(Some((key, value)))
     }
 }
 
-struct MerkCommitKVStore {
-    merk: Merk,
-    changes: BTreeMap<Vec<u8>, Op>
-}
-
-impl ReadonlyKVStore for MerkCommitKVStore {
-    fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, StoreError> {
-        unimplemented!()
+impl Map<Vec<u8>, Vec<u8>> for MerkStore {
+    fn get(&self, key: &Vec<u8>) -> Result<Option<Vec<u8>>> {
+        match self.pending.get(key) {
+            Some(op) => Ok(op.clone()),
+            None => self.merk.get(key).map_err(merk_err),
+        }
     }
 
-    fn has(&self, key: &[u8]) -> Result<bool, StoreError> {
-        unimplemented!()
+    fn has(&self, key: &Vec<u8>) -> Result<bool> {
+        Ok(self.get(key)?.is_some())
     }
+}
 
-    fn iterator(&self, start: &[u8], end: &[u8]) -> Result<Box<Iterator>, StoreError> {
-        unimplemented!()
+impl OrderedMap<Vec<u8>, Vec<u8>> for MerkStore {
+    fn iterator(&self, start: Option<&Vec<u8>>, end: Option<&Vec<u8>>) -> Result<Box<dyn Iterator<Vec<u8>, Vec<u8>> + '_>> {
+        let merk = Box::new(self.merk_iterator(start, end, false));
+        Ok(Box::new(MergeIterator::new(merk, &self.pending, start, end, false)))
     }
 
-    fn reverse_iterator(&self, start: &[u8], end: &[u8]) -> Result<Box<Iterator>, StoreError> {
-        unimplemented!()
+    fn reverse_iterator(&self, start: Option<&Vec<u8>>, end: Option<&Vec<u8>>) -> Result<Box<dyn Iterator<Vec<u8>, Vec<u8>> + '_>> {
+        let merk = Box::new(self.merk_iterator(start, end, true));
+        Ok(Box::new(MergeIterator::new(merk, &self.pending, start, end, true)))
     }
 }
 
-impl KVStore for MerkCommitKVStore {
-    fn set(&mut self, key: &[u8], value: &[u8]) -> Result<(), StoreError> {
-        unimplemented!()
+impl MutableMap<Vec<u8>, Vec<u8>> for MerkStore {
+    fn set(&mut self, key: &Vec<u8>, value: &Vec<u8>) -> Result<()> {
+        self.pending.insert(key.clone(), Some(value.clone()));
+        Ok(())
     }
 
-    fn delete(&mut self, key: &[u8]) -> Result<(), StoreError> {
-        unimplemented!()
+    fn delete(&mut self, key: &Vec<u8>) -> Result<()> {
+        self.pending.insert(key.clone(), None);
+        Ok(())
     }
 }
 
-impl <'a> Batch<'a> for MerkCommitKVStore {
-    fn new_batch(&'a mut self) -> &mut dyn Batch<'a> {
-        unimplemented!()
+impl MutableOrderedMap<Vec<u8>, Vec<u8>> for MerkStore {}
+
+impl Batch<Vec<u8>, Vec<u8>> for MerkStore {
+    fn new_batch(&mut self) -> Box<dyn Batch<Vec<u8>, Vec<u8>> + '_> {
+        Box::new(self.batch())
     }
 
-    fn write(&mut self) -> Result<(), StoreError> {
-        unimplemented!()
+    fn write(&mut self) -> Result<()> {
+        // pending writes are only applied to merk on commit, so that the tree is hashed once
+        // per block
+        Ok(())
     }
 }
 
-impl <'a> CommitKVStore<'a> for MerkCommitKVStore {
-    fn commit(&mut self) -> Result<Commit, StoreError> {
-        let mut batch = Vec::with_capacity(self.changes.len());
-        for (k, v) in self.changes.iter() {
-            batch.push((k.clone(), *v.clone()))
+impl CommitKVStore<Vec<u8>, Vec<u8>, CommitID> for MerkStore {
+    fn commit(&mut self) -> Result<CommitID> {
+        let mut batch = Vec::with_capacity(self.pending.len());
+        for (k, op) in self.pending.iter() {
+            match op {
+                Some(v) => batch.push((k.clone(), Op::Put(v.clone()))),
+                // merk fails when deleting missing keys
+                None => if self.merk.get(k).map_err(merk_err)?.is_some() {
+                    batch.push((k.clone(), Op::Delete))
+                },
+            }
         }
-        self.merk.apply(batch.as_slice())
-            .map_err(|e| StoreError::Wrap(Box::from(e)))?;
-        Ok(Commit{hash: self.merk.root_hash().to_vec() })
+        let version = self.last_commit.version + 1;
+        let aux = [(Vec::from(VERSION_KEY), Op::Put(version.to_be_bytes().to_vec()))];
+        self.merk.apply(&batch, &aux).map_err(merk_err)?;
+        self.pending.clear();
+        self.last_commit = CommitID { version, hash: self.merk.root_hash().to_vec() };
+        Ok(self.last_commit.clone())
     }
 }
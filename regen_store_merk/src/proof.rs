@@ -0,0 +1,36 @@
+use std::convert::TryInto;
+use regen_store::{Result, StoreError};
+use crate::{MerkStore, merk_err};
+
+/// The values of the queried keys, `None` for absent keys, and the encoded proof.
+pub type ValuesWithProof = (Vec<Option<Vec<u8>>>, Vec<u8>);
+
+/// Proofs are always against the last commit, writes which haven't been committed yet are
+/// neither returned nor proven.
+impl MerkStore {
+    /// Returns the committed value of each of `keys` with a proof of them (including proofs of
+    /// absence) against `last_commit().hash`. `keys` must be sorted and unique, and Merk can't
+    /// prove anything about an empty store.
+    pub fn get_with_proof(&self, keys: &[Vec<u8>]) -> Result<ValuesWithProof> {
+        let proof = self.merk.prove(keys).map_err(merk_err)?;
+        let mut values = Vec::with_capacity(keys.len());
+        for key in keys {
+            values.push(self.merk.get(key).map_err(merk_err)?);
+        }
+        Ok((values, proof))
+    }
+}
+
+pub(crate) fn root_hash(commit_hash: &[u8]) -> Result<merk::Hash> {
+    match commit_hash.try_into() {
+        Ok(hash) => Ok(hash),
+        Err(_) => Err(Box::from(StoreError::Other(format!("invalid merk root hash length {}", commit_hash.len())))),
+    }
+}
+
+/// Checks a proof from `get_with_proof` against a commit hash and returns the proven values of
+/// `keys`, which must be the sorted keys the proof was made for. Fails if the proof doesn't
+/// match the hash or doesn't cover every key.
+pub fn verify_proof(proof: &[u8], commit_hash: &[u8], keys: &[Vec<u8>]) -> Result<Vec<Option<Vec<u8>>>> {
+    merk::verify_proof(proof, keys, root_hash(commit_hash)?).map_err(merk_err)
+}
@@ -0,0 +1,61 @@
+use regen_store::{MutableMap, OrderedMap, CommitKVStore};
+use regen_store_merk::MerkStore;
+use regen_store_merk::checkpoint::MerkRestorer;
+use regen_store_test::collect;
+
+#[test]
+fn test_checkpoint_restore() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut store = MerkStore::open(dir.path().join("store")).unwrap();
+    for i in 0..2500u32 {
+        store.set(&i.to_be_bytes().to_vec(), &vec![i as u8; 8]).unwrap();
+    }
+    let commit = store.commit().unwrap();
+    let checkpoint = store.checkpoint(dir.path().join("checkpoint")).unwrap();
+    assert_eq!(checkpoint.commit(), &commit);
+    assert!(store.checkpoint(dir.path().join("checkpoint")).is_err());
+
+    // later commits don't change the checkpoint
+    store.delete(&0u32.to_be_bytes().to_vec()).unwrap();
+    store.commit().unwrap();
+
+    let mut chunks = Vec::new();
+    let mut producer = checkpoint.chunks();
+    while let Some(chunk) = producer.next_chunk().unwrap() {
+        chunks.push(chunk);
+    }
+    assert_eq!(chunks.len(), 3);
+
+    // a corrupted value doesn't match the commit hash
+    let mut restorer = MerkRestorer::new(dir.path().join("bad"), commit.clone()).unwrap();
+    for (i, chunk) in chunks.iter().enumerate() {
+        let mut chunk = chunk.clone();
+        if i == 1 {
+            let last = chunk.len() - 1;
+            chunk[last] ^= 1;
+        }
+        restorer.process_chunk(&chunk).unwrap();
+    }
+    assert!(restorer.finalize().is_err());
+    // and a truncated chunk can't be read
+    let mut restorer = MerkRestorer::new(dir.path().join("truncated"), commit.clone()).unwrap();
+    assert!(restorer.process_chunk(&chunks[0][..chunks[0].len() - 1]).is_err());
+    // nor can a missing one
+    let mut restorer = MerkRestorer::new(dir.path().join("missing"), commit.clone()).unwrap();
+    restorer.process_chunk(&chunks[0]).unwrap();
+    let res = restorer.process_chunk(&chunks[2]).and_then(|_| restorer.finalize().map(|_| ()));
+    assert!(res.is_err());
+
+    let mut restorer = MerkRestorer::new(dir.path().join("restored"), commit.clone()).unwrap();
+    for chunk in chunks.iter() {
+        restorer.process_chunk(chunk).unwrap();
+    }
+    let mut restored = restorer.finalize().unwrap();
+    assert_eq!(restored.last_commit(), &commit);
+    assert_eq!(collect(restored.iterator(None, None).unwrap()).len(), 2500);
+    checkpoint.destroy().unwrap();
+
+    // the restored store keeps committing like the original
+    restored.delete(&0u32.to_be_bytes().to_vec()).unwrap();
+    assert_eq!(restored.commit().unwrap(), store.last_commit().clone());
+}
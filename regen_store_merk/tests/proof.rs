@@ -0,0 +1,37 @@
+use regen_store::{MutableMap, CommitKVStore};
+use regen_store_merk::MerkStore;
+use regen_store_merk::proof::verify_proof;
+
+fn k(s: &str) -> Vec<u8> {
+    Vec::from(s.as_bytes())
+}
+
+#[test]
+fn test_proofs() {
+    let mut store = MerkStore::open_temporary().unwrap();
+    for key in &["a", "b", "c", "d", "f", "g", "h"] {
+        store.set(&k(key), &k(&key.to_uppercase())).unwrap();
+    }
+    let commit = store.commit().unwrap();
+    // uncommitted writes aren't proven
+    store.set(&k("e"), &k("E")).unwrap();
+
+    let keys = vec![k("b"), k("bb"), k("e")];
+    let (values, proof) = store.get_with_proof(&keys).unwrap();
+    assert_eq!(values, vec![Some(k("B")), None, None]);
+    assert_eq!(verify_proof(&proof, &commit.hash, &keys).unwrap(), values);
+
+    let mut bad_hash = commit.hash.clone();
+    bad_hash[0] ^= 1;
+    assert!(verify_proof(&proof, &bad_hash, &keys).is_err());
+    assert!(verify_proof(&proof, &commit.hash[1..], &keys).is_err());
+
+    // keys must be sorted
+    assert!(store.get_with_proof(&[k("c"), k("a")]).is_err());
+
+    // proofs of the new commit
+    let commit = store.commit().unwrap();
+    let (values, proof) = store.get_with_proof(&[k("e"), k("h")]).unwrap();
+    assert_eq!(values, vec![Some(k("E")), Some(k("H"))]);
+    assert_eq!(verify_proof(&proof, &commit.hash, &[k("e"), k("h")]).unwrap(), values);
+}
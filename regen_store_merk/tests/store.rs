@@ -0,0 +1,75 @@
+use regen_store::{Map, OrderedMap, MutableMap, Batch, CommitKVStore};
+use regen_store_merk::MerkStore;
+use regen_store_test::{store_conformance_tests, collect};
+
+fn new_store() -> MerkStore {
+    MerkStore::open_temporary().unwrap()
+}
+
+store_conformance_tests!(new_store);
+
+#[test]
+fn test_reopen() {
+    let dir = tempfile::tempdir().unwrap();
+    let commit = {
+        let mut store = MerkStore::open(dir.path()).unwrap();
+        store.set(&b"a".to_vec(), &b"1".to_vec()).unwrap();
+        store.set(&b"b".to_vec(), &b"2".to_vec()).unwrap();
+        store.commit().unwrap();
+        store.delete(&b"a".to_vec()).unwrap();
+        let commit = store.commit().unwrap();
+        // uncommitted writes are lost
+        store.set(&b"c".to_vec(), &b"3".to_vec()).unwrap();
+        commit
+    };
+    let store = MerkStore::open(dir.path()).unwrap();
+    assert_eq!(store.last_commit(), &commit);
+    assert_eq!(commit.version, 2);
+    assert_eq!(store.get(&b"a".to_vec()).unwrap(), None);
+    assert_eq!(collect(store.iterator(None, None).unwrap()), vec![(b"b".to_vec(), b"2".to_vec())]);
+}
+
+#[test]
+fn test_tx_rollback() {
+    let mut store = new_store();
+    let mut expected = new_store();
+    {
+        let mut block = store.batch();
+        for (i, ok) in [true, false, true].iter().enumerate() {
+            let mut tx = block.new_batch();
+            tx.set(&vec![i as u8], &b"x".to_vec()).unwrap();
+            {
+                let mut msg = tx.new_batch();
+                msg.set(&vec![i as u8, 0], &b"y".to_vec()).unwrap();
+                assert_eq!(msg.get(&vec![i as u8]).unwrap(), Some(b"x".to_vec()));
+                msg.write().unwrap();
+            }
+            if *ok {
+                tx.write().unwrap();
+            }
+        }
+        block.write().unwrap();
+    }
+    for i in [0u8, 2].iter() {
+        expected.set(&vec![*i], &b"x".to_vec()).unwrap();
+        expected.set(&vec![*i, 0], &b"y".to_vec()).unwrap();
+    }
+    assert!(!store.has(&vec![1]).unwrap());
+    assert_eq!(store.commit().unwrap(), expected.commit().unwrap());
+}
+
+#[test]
+fn test_iterate_committed() {
+    let mut store = new_store();
+    let keys: Vec<Vec<u8>> = (0u8..20).map(|i| vec![i * 2]).collect();
+    for key in keys.iter() {
+        store.set(key, key).unwrap();
+    }
+    store.commit().unwrap();
+    let entries = |it| collect(it).into_iter().map(|(k, _)| k[0]).collect::<Vec<u8>>();
+    assert_eq!(entries(store.iterator(None, None).unwrap()), (0..20).map(|i| i * 2).collect::<Vec<u8>>());
+    assert_eq!(entries(store.iterator(Some(&vec![5]), Some(&vec![12])).unwrap()), vec![6, 8, 10]);
+    assert_eq!(entries(store.reverse_iterator(Some(&vec![5]), Some(&vec![12])).unwrap()), vec![10, 8, 6]);
+    assert_eq!(entries(store.reverse_iterator(None, Some(&vec![4])).unwrap()), vec![2, 0]);
+    assert_eq!(entries(store.iterator(Some(&vec![39]), None).unwrap()), Vec::<u8>::new());
+}
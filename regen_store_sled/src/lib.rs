@@ -0,0 +1,205 @@
+use std::collections::BTreeMap;
+use std::convert::TryInto;
+use std::path::Path;
+use blake2::{Blake2b, Digest};
+use sled::Transactional;
+use sled::transaction::TransactionResult;
+use regen_store::{Map, OrderedMap, MutableMap, MutableOrderedMap, Batch, CommitKVStore, CommitID, Iterator, Result, StoreError, range_bounds, is_empty_range};
+use regen_store::cache::{CacheBatch, MergeIterator};
+
+const COMMIT_KEY: &[u8] = b"commit";
+
+/// A persistent store on top of sled. Writes are kept in memory until `commit`, which applies
+/// them to disk together with the new `CommitID` in a single sled transaction and flushes, so
+/// after a crash the store is at its last commit.
+///
+/// The commit hash chains the previous commit hash, the version and the net changes of the
+/// commit, leaving out writes which don't change the committed state. It identifies the history
+/// of states rather than the current state alone, so stores which reach the same state through
+/// different commits have different hashes.
+pub struct SledStore {
+    db: sled::Db,
+    data: sled::Tree,
+    meta: sled::Tree,
+    pending: BTreeMap<Vec<u8>, Option<Vec<u8>>>,
+    last_commit: CommitID,
+}
+
+impl SledStore {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Self::from_db(sled::open(path)?)
+    }
+
+    /// Opens a store in a temporary directory which is removed when the store is dropped.
+    pub fn open_temporary() -> Result<Self> {
+        Self::from_db(sled::Config::new().temporary(true).open()?)
+    }
+
+    fn from_db(db: sled::Db) -> Result<Self> {
+        let data = db.open_tree("data")?;
+        let meta = db.open_tree("meta")?;
+        let last_commit = match meta.get(COMMIT_KEY)? {
+            None => CommitID::default(),
+            Some(bz) => decode_commit(&bz)?,
+        };
+        Ok(SledStore {
+            db,
+            data,
+            meta,
+            pending: BTreeMap::new(),
+            last_commit,
+        })
+    }
+
+    pub fn last_commit(&self) -> &CommitID {
+        &self.last_commit
+    }
+}
+
+fn encode_commit(commit: &CommitID) -> Vec<u8> {
+    let mut res = Vec::with_capacity(8 + commit.hash.len());
+    res.extend_from_slice(&commit.version.to_be_bytes());
+    res.extend_from_slice(&commit.hash);
+    res
+}
+
+fn decode_commit(bz: &[u8]) -> Result<CommitID> {
+    if bz.len() < 8 {
+        return Err(Box::from(StoreError::Other(String::from("corrupt commit info"))));
+    }
+    Ok(CommitID {
+        version: u64::from_be_bytes(bz[..8].try_into()?),
+        hash: Vec::from(&bz[8..]),
+    })
+}
+
+struct SledIterator {
+    iter: Option<sled::Iter>,
+    reverse: bool,
+}
+
+impl Iterator<Vec<u8>, Vec<u8>> for SledIterator {
+    fn next(&mut self) -> Result<Option<(Vec<u8>, Vec<u8>)>> {
+        let iter = match &mut self.iter {
+            None => return Ok(None),
+            Some(iter) => iter,
+        };
+        let next = if self.reverse {
+            iter.next_back()
+        } else {
+            iter.next()
+        };
+        match next {
+            None => Ok(None),
+            Some(res) => {
+                let (k, v) = res?;
+                Ok(Some((k.to_vec(), v.to_vec())))
+            }
+        }
+    }
+}
+
+impl SledStore {
+    fn sled_iterator(&self, start: Option<&Vec<u8>>, end: Option<&Vec<u8>>, reverse: bool) -> SledIterator {
+        let iter = if is_empty_range(start, end) {
+            None
+        } else {
+            Some(self.data.range(range_bounds(start, end)))
+        };
+        SledIterator { iter, reverse }
+    }
+}
+
+impl Map<Vec<u8>, Vec<u8>> for SledStore {
+    fn get(&self, key: &Vec<u8>) -> Result<Option<Vec<u8>>> {
+        match self.pending.get(key) {
+            Some(op) => Ok(op.clone()),
+            None => Ok(self.data.get(key)?.map(|v| v.to_vec())),
+        }
+    }
+
+    fn has(&self, key: &Vec<u8>) -> Result<bool> {
+        match self.pending.get(key) {
+            Some(op) => Ok(op.is_some()),
+            None => Ok(self.data.contains_key(key)?),
+        }
+    }
+}
+
+impl OrderedMap<Vec<u8>, Vec<u8>> for SledStore {
+    fn iterator(&self, start: Option<&Vec<u8>>, end: Option<&Vec<u8>>) -> Result<Box<dyn Iterator<Vec<u8>, Vec<u8>> + '_>> {
+        let disk = Box::new(self.sled_iterator(start, end, false));
+        Ok(Box::new(MergeIterator::new(disk, &self.pending, start, end, false)))
+    }
+
+    fn reverse_iterator(&self, start: Option<&Vec<u8>>, end: Option<&Vec<u8>>) -> Result<Box<dyn Iterator<Vec<u8>, Vec<u8>> + '_>> {
+        let disk = Box::new(self.sled_iterator(start, end, true));
+        Ok(Box::new(MergeIterator::new(disk, &self.pending, start, end, true)))
+    }
+}
+
+impl MutableMap<Vec<u8>, Vec<u8>> for SledStore {
+    fn set(&mut self, key: &Vec<u8>, value: &Vec<u8>) -> Result<()> {
+        self.pending.insert(key.clone(), Some(value.clone()));
+        Ok(())
+    }
+
+    fn delete(&mut self, key: &Vec<u8>) -> Result<()> {
+        self.pending.insert(key.clone(), None);
+        Ok(())
+    }
+}
+
+impl MutableOrderedMap<Vec<u8>, Vec<u8>> for SledStore {}
+
+impl Batch<Vec<u8>, Vec<u8>> for SledStore {
+    fn new_batch(&mut self) -> Box<dyn Batch<Vec<u8>, Vec<u8>> + '_> {
+        Box::new(CacheBatch::new(self))
+    }
+
+    fn write(&mut self) -> Result<()> {
+        // pending writes are only applied on commit, together with the commit info
+        Ok(())
+    }
+}
+
+impl CommitKVStore<Vec<u8>, Vec<u8>, CommitID> for SledStore {
+    fn commit(&mut self) -> Result<CommitID> {
+        let version = self.last_commit.version + 1;
+        let mut hasher = Blake2b::new();
+        hasher.input(&self.last_commit.hash);
+        hasher.input(version.to_be_bytes());
+        let mut batch = sled::Batch::default();
+        for (k, op) in self.pending.iter() {
+            if self.data.get(k)?.as_deref() == op.as_deref() {
+                continue;
+            }
+            hasher.input((k.len() as u64).to_be_bytes());
+            hasher.input(k);
+            match op {
+                None => {
+                    hasher.input([0]);
+                    batch.remove(k.as_slice());
+                }
+                Some(v) => {
+                    hasher.input([1]);
+                    hasher.input((v.len() as u64).to_be_bytes());
+                    hasher.input(v);
+                    batch.insert(k.as_slice(), v.as_slice());
+                }
+            }
+        }
+        let commit = CommitID { version, hash: hasher.result().to_vec() };
+        let encoded = encode_commit(&commit);
+        let res: TransactionResult<(), sled::Error> = (&self.data, &self.meta).transaction(|(data, meta)| {
+            data.apply_batch(&batch)?;
+            meta.insert(COMMIT_KEY, encoded.as_slice())?;
+            Ok(())
+        });
+        res?;
+        self.db.flush()?;
+        self.pending.clear();
+        self.last_commit = commit.clone();
+        Ok(commit)
+    }
+}
@@ -0,0 +1,8 @@
+use regen_store_sled::SledStore;
+use regen_store_test::store_conformance_tests;
+
+fn new_store() -> SledStore {
+    SledStore::open_temporary().unwrap()
+}
+
+store_conformance_tests!(new_store);
@@ -0,0 +1,149 @@
+use std::io::{BufRead, BufReader};
+use std::process::{Command, Stdio};
+use std::time::Duration;
+use regen_store::{Map, MutableMap, OrderedMap, Batch, CommitKVStore};
+use regen_store_sled::SledStore;
+
+const CRASH_DIR_ENV: &str = "REGEN_SLED_CRASH_DIR";
+const NUM_KEYS: u64 = 100;
+const COMMIT_EVERY: u64 = 5;
+const COMMITTED: &str = "committed ";
+
+fn key(i: u64) -> Vec<u8> {
+    i.to_be_bytes().to_vec()
+}
+
+// Run by test_crash_recovery in a child process which gets killed part way through. Every
+// round sets all keys to the round number and every COMMIT_EVERY rounds are committed, then
+// the version is reported on stdout.
+#[test]
+#[ignore]
+fn crash_writer() {
+    let dir = match std::env::var(CRASH_DIR_ENV) {
+        Err(_) => return,
+        Ok(dir) => dir,
+    };
+    let mut store = SledStore::open(dir).unwrap();
+    let mut version = store.last_commit().version;
+    loop {
+        for round in version * COMMIT_EVERY + 1..=(version + 1) * COMMIT_EVERY {
+            let value = round.to_string().into_bytes();
+            for i in 0..NUM_KEYS {
+                store.set(&key(i), &value).unwrap();
+            }
+        }
+        version = store.commit().unwrap().version;
+        println!("{}{}", COMMITTED, version);
+    }
+}
+
+// sled's background flusher can hold the file lock briefly after a store is dropped
+fn reopen(path: &std::path::Path) -> SledStore {
+    for _ in 0..50 {
+        if let Ok(store) = SledStore::open(path) {
+            return store;
+        }
+        std::thread::sleep(Duration::from_millis(20));
+    }
+    SledStore::open(path).unwrap()
+}
+
+// the round all keys are set to, 0 if none are
+fn read_round(store: &SledStore) -> u64 {
+    let mut it = store.iterator(None, None).unwrap();
+    let mut values = Vec::new();
+    while let Some((_, v)) = it.next().unwrap() {
+        values.push(String::from_utf8(v).unwrap().parse::<u64>().unwrap());
+    }
+    if values.is_empty() {
+        return 0;
+    }
+    assert_eq!(values.len() as u64, NUM_KEYS);
+    assert!(values.iter().all(|v| *v == values[0]), "torn write: {:?}", values);
+    values[0]
+}
+
+#[test]
+fn test_crash_recovery() {
+    let dir = tempfile::tempdir().unwrap();
+    let exe = std::env::current_exe().unwrap();
+    let mut last_committed = 0;
+    for attempt in 1..=5 {
+        let mut child = Command::new(&exe)
+            .args(["crash_writer", "--exact", "--ignored", "--nocapture"])
+            .env(CRASH_DIR_ENV, dir.path())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .unwrap();
+        // kill the writer at some point after it reported a few more commits
+        let mut reported = last_committed;
+        for line in BufReader::new(child.stdout.take().unwrap()).lines() {
+            if let Some(version) = line.unwrap().strip_prefix(COMMITTED) {
+                reported = version.parse().unwrap();
+            }
+            if reported >= last_committed + attempt {
+                break;
+            }
+        }
+        child.kill().unwrap();
+        child.wait().unwrap();
+
+        let store = reopen(dir.path());
+        let committed = store.last_commit().version;
+        // reported commits are durable and uncommitted rounds are lost
+        assert!(committed >= reported, "committed {} < reported {}", committed, reported);
+        assert_eq!(read_round(&store), committed * COMMIT_EVERY);
+        last_committed = committed;
+    }
+}
+
+#[test]
+fn test_commit_durable() {
+    let dir = tempfile::tempdir().unwrap();
+    let commit = {
+        let mut store = SledStore::open(dir.path()).unwrap();
+        store.set(&b"a".to_vec(), &b"1".to_vec()).unwrap();
+        store.set(&b"b".to_vec(), &b"2".to_vec()).unwrap();
+        {
+            let mut batch = store.new_batch();
+            batch.delete(&b"a".to_vec()).unwrap();
+            batch.set(&b"c".to_vec(), &b"3".to_vec()).unwrap();
+            batch.write().unwrap();
+        }
+        store.commit().unwrap()
+    };
+    let store = reopen(dir.path());
+    assert_eq!(store.last_commit(), &commit);
+    assert_eq!(commit.version, 1);
+    assert!(!store.has(&b"a".to_vec()).unwrap());
+    assert_eq!(store.get(&b"b".to_vec()).unwrap(), Some(b"2".to_vec()));
+    assert_eq!(store.get(&b"c".to_vec()).unwrap(), Some(b"3".to_vec()));
+
+    let mut it = store.reverse_iterator(None, Some(&b"c".to_vec())).unwrap();
+    assert_eq!(it.next().unwrap(), Some((b"b".to_vec(), b"2".to_vec())));
+    assert_eq!(it.next().unwrap(), None);
+}
+
+#[test]
+fn test_commit_hash_deterministic() {
+    let dir1 = tempfile::tempdir().unwrap();
+    let dir2 = tempfile::tempdir().unwrap();
+    let mut s1 = SledStore::open(dir1.path()).unwrap();
+    let mut s2 = SledStore::open(dir2.path()).unwrap();
+    s1.set(&b"a".to_vec(), &b"1".to_vec()).unwrap();
+    s1.write().unwrap();
+    s1.set(&b"b".to_vec(), &b"2".to_vec()).unwrap();
+    s2.set(&b"b".to_vec(), &b"2".to_vec()).unwrap();
+    s2.set(&b"a".to_vec(), &b"1".to_vec()).unwrap();
+    assert_eq!(s1.commit().unwrap(), s2.commit().unwrap());
+
+    // writes which don't change the state don't change the hash
+    s1.set(&b"c".to_vec(), &b"3".to_vec()).unwrap();
+    s1.delete(&b"c".to_vec()).unwrap();
+    s1.set(&b"a".to_vec(), &b"1".to_vec()).unwrap();
+    s2.set(&b"d".to_vec(), &b"4".to_vec()).unwrap();
+    s2.write().unwrap();
+    s2.delete(&b"d".to_vec()).unwrap();
+    assert_eq!(s1.commit().unwrap(), s2.commit().unwrap());
+}
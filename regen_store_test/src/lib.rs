@@ -0,0 +1,302 @@
+use std::collections::BTreeMap;
+use proptest::prelude::*;
+use proptest::test_runner::TestRunner;
+use regen_store::{MutableOrderedMap, Batch, CommitKVStore, CommitID, Iterator};
+
+/// Conformance checks which every `regen_store` backend should pass. Backends normally just
+/// invoke `store_conformance_tests!` with a function creating a fresh, empty store.
+#[macro_export]
+macro_rules! store_conformance_tests {
+    ($new_store:expr) => {
+        #[test]
+        fn conformance_get_set_delete() {
+            $crate::check_get_set_delete(&mut $new_store());
+        }
+
+        #[test]
+        fn conformance_iterator_bounds() {
+            $crate::check_iterator_bounds(&mut $new_store());
+        }
+
+        #[test]
+        fn conformance_reverse_iterator() {
+            $crate::check_reverse_iterator(&mut $new_store());
+        }
+
+        #[test]
+        fn conformance_batch_isolation() {
+            $crate::check_batch_isolation(&mut $new_store());
+        }
+
+        #[test]
+        fn conformance_nested_batches() {
+            $crate::check_nested_batches(&mut $new_store());
+        }
+
+        #[test]
+        fn conformance_commit_determinism() {
+            $crate::check_commit_determinism($new_store);
+        }
+
+        #[test]
+        fn conformance_model() {
+            $crate::check_model($new_store);
+        }
+    };
+}
+
+fn k(s: &str) -> Vec<u8> {
+    Vec::from(s.as_bytes())
+}
+
+pub fn collect(mut it: Box<dyn Iterator<Vec<u8>, Vec<u8>> + '_>) -> Vec<(Vec<u8>, Vec<u8>)> {
+    let mut res = Vec::new();
+    while let Some(kv) = it.next().unwrap() {
+        res.push(kv);
+    }
+    res
+}
+
+fn keys(entries: Vec<(Vec<u8>, Vec<u8>)>) -> Vec<Vec<u8>> {
+    entries.into_iter().map(|(k, _)| k).collect()
+}
+
+fn set_all<S: MutableOrderedMap<Vec<u8>, Vec<u8>> + ?Sized>(store: &mut S, ks: &[&str]) {
+    for key in ks {
+        store.set(&k(key), &k(&key.to_uppercase())).unwrap();
+    }
+}
+
+pub fn check_get_set_delete(store: &mut dyn MutableOrderedMap<Vec<u8>, Vec<u8>>) {
+    assert_eq!(store.get(&k("a")).unwrap(), None);
+    assert!(!store.has(&k("a")).unwrap());
+
+    store.set(&k("a"), &k("1")).unwrap();
+    assert_eq!(store.get(&k("a")).unwrap(), Some(k("1")));
+    assert!(store.has(&k("a")).unwrap());
+
+    store.set(&k("a"), &k("2")).unwrap();
+    assert_eq!(store.get(&k("a")).unwrap(), Some(k("2")));
+
+    // empty values are values
+    store.set(&k("b"), &Vec::new()).unwrap();
+    assert_eq!(store.get(&k("b")).unwrap(), Some(Vec::new()));
+    assert!(store.has(&k("b")).unwrap());
+
+    store.delete(&k("a")).unwrap();
+    assert_eq!(store.get(&k("a")).unwrap(), None);
+    assert!(!store.has(&k("a")).unwrap());
+
+    // deleting a missing key is fine
+    store.delete(&k("c")).unwrap();
+    assert_eq!(keys(collect(store.iterator(None, None).unwrap())), vec![k("b")]);
+}
+
+pub fn check_iterator_bounds(store: &mut dyn MutableOrderedMap<Vec<u8>, Vec<u8>>) {
+    assert!(collect(store.iterator(None, None).unwrap()).is_empty());
+    set_all(store, &["b", "d", "f", "f0", "h"]);
+
+    assert_eq!(
+        collect(store.iterator(None, None).unwrap()),
+        vec![(k("b"), k("B")), (k("d"), k("D")), (k("f"), k("F")), (k("f0"), k("F0")), (k("h"), k("H"))]
+    );
+    // start is inclusive, end is exclusive
+    assert_eq!(keys(collect(store.iterator(Some(&k("d")), Some(&k("h"))).unwrap())), vec![k("d"), k("f"), k("f0")]);
+    // bounds which aren't keys
+    assert_eq!(keys(collect(store.iterator(Some(&k("c")), Some(&k("g"))).unwrap())), vec![k("d"), k("f"), k("f0")]);
+    assert_eq!(keys(collect(store.iterator(None, Some(&k("d"))).unwrap())), vec![k("b")]);
+    assert_eq!(keys(collect(store.iterator(Some(&k("f0")), None).unwrap())), vec![k("f0"), k("h")]);
+    // empty and inverted ranges
+    assert!(collect(store.iterator(Some(&k("d")), Some(&k("d"))).unwrap()).is_empty());
+    assert!(collect(store.iterator(Some(&k("h")), Some(&k("b"))).unwrap()).is_empty());
+    assert!(collect(store.iterator(Some(&k("i")), None).unwrap()).is_empty());
+    assert!(collect(store.iterator(None, Some(&k("a"))).unwrap()).is_empty());
+
+    // deleted keys don't show up
+    store.delete(&k("f")).unwrap();
+    assert_eq!(keys(collect(store.iterator(Some(&k("d")), Some(&k("h"))).unwrap())), vec![k("d"), k("f0")]);
+}
+
+pub fn check_reverse_iterator(store: &mut dyn MutableOrderedMap<Vec<u8>, Vec<u8>>) {
+    assert!(collect(store.reverse_iterator(None, None).unwrap()).is_empty());
+    set_all(store, &["b", "d", "f", "f0", "h"]);
+
+    assert_eq!(keys(collect(store.reverse_iterator(None, None).unwrap())), vec![k("h"), k("f0"), k("f"), k("d"), k("b")]);
+    assert_eq!(keys(collect(store.reverse_iterator(Some(&k("d")), Some(&k("h"))).unwrap())), vec![k("f0"), k("f"), k("d")]);
+    assert_eq!(keys(collect(store.reverse_iterator(Some(&k("c")), Some(&k("f0"))).unwrap())), vec![k("f"), k("d")]);
+    assert_eq!(keys(collect(store.reverse_iterator(Some(&k("f")), None).unwrap())), vec![k("h"), k("f0"), k("f")]);
+    assert!(collect(store.reverse_iterator(Some(&k("d")), Some(&k("d"))).unwrap()).is_empty());
+    assert!(collect(store.reverse_iterator(Some(&k("h")), Some(&k("b"))).unwrap()).is_empty());
+}
+
+pub fn check_batch_isolation(store: &mut dyn Batch<Vec<u8>, Vec<u8>>) {
+    set_all(store, &["a", "c"]);
+    {
+        let mut batch = store.new_batch();
+        batch.set(&k("b"), &k("B")).unwrap();
+        batch.delete(&k("c")).unwrap();
+        batch.set(&k("a"), &k("A2")).unwrap();
+        // read your writes
+        assert_eq!(batch.get(&k("a")).unwrap(), Some(k("A2")));
+        assert!(batch.has(&k("b")).unwrap());
+        assert!(!batch.has(&k("c")).unwrap());
+        assert_eq!(collect(batch.iterator(None, None).unwrap()), vec![(k("a"), k("A2")), (k("b"), k("B"))]);
+        assert_eq!(keys(collect(batch.reverse_iterator(None, None).unwrap())), vec![k("b"), k("a")]);
+        assert_eq!(batch.count(None, None).unwrap(), 2);
+        assert_eq!(batch.count(Some(&k("b")), None).unwrap(), 1);
+        // dropped without writing
+    }
+    assert_eq!(collect(store.iterator(None, None).unwrap()), vec![(k("a"), k("A")), (k("c"), k("C"))]);
+    {
+        let mut batch = store.new_batch();
+        batch.set(&k("b"), &k("B")).unwrap();
+        batch.delete(&k("c")).unwrap();
+        batch.write().unwrap();
+    }
+    assert_eq!(collect(store.iterator(None, None).unwrap()), vec![(k("a"), k("A")), (k("b"), k("B"))]);
+}
+
+pub fn check_nested_batches(store: &mut dyn Batch<Vec<u8>, Vec<u8>>) {
+    set_all(store, &["a"]);
+    {
+        let mut outer = store.new_batch();
+        outer.set(&k("b"), &k("B")).unwrap();
+        {
+            let mut inner = outer.new_batch();
+            inner.set(&k("c"), &k("C")).unwrap();
+            inner.delete(&k("a")).unwrap();
+            assert_eq!(keys(collect(inner.iterator(None, None).unwrap())), vec![k("b"), k("c")]);
+        }
+        assert_eq!(keys(collect(outer.iterator(None, None).unwrap())), vec![k("a"), k("b")]);
+        {
+            let mut inner = outer.new_batch();
+            inner.set(&k("c"), &k("C")).unwrap();
+            inner.delete(&k("a")).unwrap();
+            inner.write().unwrap();
+        }
+        assert_eq!(keys(collect(outer.iterator(None, None).unwrap())), vec![k("b"), k("c")]);
+        outer.write().unwrap();
+    }
+    assert_eq!(keys(collect(store.iterator(None, None).unwrap())), vec![k("b"), k("c")]);
+}
+
+pub fn check_commit_determinism<S, F>(new_store: F)
+    where S: CommitKVStore<Vec<u8>, Vec<u8>, CommitID>, F: Fn() -> S {
+    let apply = |store: &mut S| -> Vec<CommitID> {
+        let mut commits = Vec::new();
+        set_all(store, &["a", "b", "c"]);
+        commits.push(store.commit().unwrap());
+        {
+            let mut batch = store.new_batch();
+            batch.delete(&k("b")).unwrap();
+            batch.set(&k("d"), &k("D")).unwrap();
+            batch.write().unwrap();
+        }
+        commits.push(store.commit().unwrap());
+        // nothing changed
+        commits.push(store.commit().unwrap());
+        commits
+    };
+    let mut s1 = new_store();
+    let mut s2 = new_store();
+    let c1 = apply(&mut s1);
+    let c2 = apply(&mut s2);
+    assert_eq!(c1, c2);
+    assert!(c1[1].version > c1[0].version && c1[2].version > c1[1].version);
+    assert_ne!(c1[0].hash, c1[1].hash);
+    assert_eq!(collect(s1.iterator(None, None).unwrap()), collect(s2.iterator(None, None).unwrap()));
+
+    s2.set(&k("e"), &k("E")).unwrap();
+    assert_ne!(s1.commit().unwrap().hash, s2.commit().unwrap().hash);
+}
+
+#[derive(Debug, Clone)]
+pub enum Op {
+    Set(Vec<u8>, Vec<u8>),
+    Delete(Vec<u8>),
+    /// Applies the ops in a child batch, writing it if the flag is set.
+    Batch(Vec<Op>, bool),
+    Commit,
+}
+
+fn key_strategy() -> impl Strategy<Value = Vec<u8>> {
+    // a small key space so that ops collide
+    prop::collection::vec(0u8..4, 0..3)
+}
+
+pub fn op_strategy() -> impl Strategy<Value = Op> {
+    let leaf = prop_oneof![
+        4 => (key_strategy(), prop::collection::vec(any::<u8>(), 0..4)).prop_map(|(k, v)| Op::Set(k, v)),
+        2 => key_strategy().prop_map(Op::Delete),
+        1 => Just(Op::Commit),
+    ];
+    leaf.prop_recursive(2, 32, 8, |inner| {
+        (prop::collection::vec(inner, 0..8), any::<bool>()).prop_map(|(ops, write)| Op::Batch(ops, write))
+    })
+}
+
+fn apply_op<S: CommitKVStore<Vec<u8>, Vec<u8>, CommitID>>(store: &mut S, model: &mut BTreeMap<Vec<u8>, Vec<u8>>, op: &Op) {
+    match op {
+        Op::Commit => {
+            store.commit().unwrap();
+        }
+        _ => apply_batch_op(store, model, op),
+    }
+}
+
+fn apply_batch_op(store: &mut dyn Batch<Vec<u8>, Vec<u8>>, model: &mut BTreeMap<Vec<u8>, Vec<u8>>, op: &Op) {
+    match op {
+        Op::Set(k, v) => {
+            store.set(k, v).unwrap();
+            model.insert(k.clone(), v.clone());
+        }
+        Op::Delete(k) => {
+            store.delete(k).unwrap();
+            model.remove(k);
+        }
+        // commits only happen at the top level
+        Op::Commit => {}
+        Op::Batch(ops, write) => {
+            let mut child_model = model.clone();
+            let mut batch = store.new_batch();
+            for op in ops {
+                apply_batch_op(batch.as_mut(), &mut child_model, op);
+            }
+            check_matches_model(batch.as_ref(), &child_model);
+            if *write {
+                batch.write().unwrap();
+                *model = child_model;
+            }
+        }
+    }
+}
+
+pub fn check_matches_model<S: MutableOrderedMap<Vec<u8>, Vec<u8>> + ?Sized>(store: &S, model: &BTreeMap<Vec<u8>, Vec<u8>>) {
+    let expected: Vec<_> = model.iter().map(|(k, v)| (k.clone(), v.clone())).collect();
+    assert_eq!(collect(store.iterator(None, None).unwrap()), expected);
+    let mut reversed = expected;
+    reversed.reverse();
+    assert_eq!(collect(store.reverse_iterator(None, None).unwrap()), reversed);
+    for key in model.keys() {
+        assert_eq!(store.get(key).unwrap().as_ref(), model.get(key));
+    }
+    let (start, end) = (vec![1], vec![2, 1]);
+    let ranged: Vec<_> = model.range(start.clone()..end.clone()).map(|(k, v)| (k.clone(), v.clone())).collect();
+    assert_eq!(collect(store.iterator(Some(&start), Some(&end)).unwrap()), ranged);
+    assert_eq!(store.count(Some(&start), Some(&end)).unwrap(), ranged.len() as u64);
+}
+
+/// Applies random sequences of ops to fresh stores and checks them against a `BTreeMap`.
+pub fn check_model<S, F>(new_store: F)
+    where S: CommitKVStore<Vec<u8>, Vec<u8>, CommitID>, F: Fn() -> S {
+    let mut runner = TestRunner::default();
+    runner.run(&prop::collection::vec(op_strategy(), 0..32), |ops| {
+        let mut store = new_store();
+        let mut model = BTreeMap::new();
+        for op in ops.iter() {
+            apply_op(&mut store, &mut model, op);
+            check_matches_model(&store, &model);
+        }
+        Ok(())
+    }).unwrap();
+}
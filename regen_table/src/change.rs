@@ -0,0 +1,70 @@
+use regen_context::event::{Event, TypedEvent};
+use crate::StoreContext;
+use crate::table::Undo;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChangeOp {
+    Create,
+    Update,
+    Delete,
+}
+
+impl ChangeOp {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ChangeOp::Create => "create",
+            ChangeOp::Update => "update",
+            ChangeOp::Delete => "delete",
+        }
+    }
+}
+
+/// A row saved or deleted by a table with `TableImpl::emit_changes`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChangeEvent {
+    pub table: String,
+    /// The encoded primary key, see `key::decode_key`.
+    pub primary_key: Vec<u8>,
+    pub op: ChangeOp,
+    /// The fields an update changed, if the table lists them.
+    pub changed_fields: Option<Vec<String>>,
+}
+
+/// Emitted as a `table_change` event with the primary key in hex, so that indexers can follow
+/// the changes of a table.
+impl TypedEvent for ChangeEvent {
+    fn to_event(&self) -> Event {
+        let key: String = self.primary_key.iter().map(|b| format!("{:02x}", b)).collect();
+        let mut event = Event::new("table_change")
+            .indexed_attr("table", &self.table)
+            .attr("op", self.op.as_str())
+            .attr("primary_key", &key);
+        if let Some(fields) = &self.changed_fields {
+            event = event.attr("changed_fields", &fields.join(","));
+        }
+        event
+    }
+}
+
+/// Lists the names of the fields which differ between the old and new value of a row.
+pub type ChangedFieldsFn<V> = Box<dyn Fn(&V, &V) -> Vec<String>>;
+
+pub(crate) struct ChangeEmitter<V> {
+    pub(crate) table: String,
+    pub(crate) changed_fields: Option<ChangedFieldsFn<V>>,
+}
+
+impl<V> ChangeEmitter<V> {
+    // buffers the change in `undo`, which emits it once the whole save or delete succeeds
+    pub(crate) fn emit(&self, ctx: &dyn StoreContext, undo: &mut Undo, primary_key: &[u8], old: Option<&V>, new: Option<&V>) {
+        if ctx.events().is_none() {
+            return;
+        }
+        let (op, changed_fields) = match (old, new) {
+            (None, _) => (ChangeOp::Create, None),
+            (Some(_), None) => (ChangeOp::Delete, None),
+            (Some(old), Some(new)) => (ChangeOp::Update, self.changed_fields.as_ref().map(|f| f(old, new))),
+        };
+        undo.emit(ChangeEvent { table: self.table.clone(), primary_key: Vec::from(primary_key), op, changed_fields });
+    }
+}
@@ -0,0 +1,124 @@
+use std::rc::Rc;
+use crate::{TableInterceptor, StoreContext, StoreKey, TableError, TableImpl, SecondaryIndex};
+use crate::TableError::{ForeignKeyConstraint, Other, Wrap};
+use crate::key::{KeyPart, encode_key, decode_key};
+use crate::table::{Undo, row_key};
+
+/// What deleting a row does to the rows referencing it.
+pub enum OnDelete<V> {
+    /// The delete fails with `TableError::ForeignKeyConstraint`.
+    Restrict,
+    /// The referencing rows are deleted too.
+    Cascade,
+    /// The referencing rows are saved after clearing the reference with the given function.
+    SetNull(Box<dyn Fn(&mut V)>),
+}
+
+/// Returns the primary key referenced by a row, if any.
+pub type ReferenceFn<PK, V> = Box<dyn Fn(&V) -> Option<PK>>;
+
+type FieldFn<PK, V> = Rc<dyn Fn(&V) -> Option<PK>>;
+
+/// A field of the rows of a child table referencing the primary key of a parent table, created
+/// by `TableImpl::add_foreign_key` on the child. Saving a child row fails if the referenced
+/// parent row is missing. Once the child table is complete, pass this to
+/// `TableImpl::add_referencing_table` on the parent to handle deletes.
+pub struct ForeignKey<PK, V> {
+    name: String,
+    field: FieldFn<PK, V>,
+    index: SecondaryIndex<Option<PK>, V>,
+}
+
+impl<PK: KeyPart + 'static, V: 'static> ForeignKey<PK, V> {
+    /// The index of the child rows by referenced primary key.
+    pub fn index(&self) -> &SecondaryIndex<Option<PK>, V> {
+        &self.index
+    }
+}
+
+// checks that the row referenced by a child row exists
+struct References<PK, V> {
+    name: String,
+    parent: StoreKey,
+    field: FieldFn<PK, V>,
+}
+
+impl<K, PK: KeyPart, V> TableInterceptor<K, V> for References<PK, V> {
+    fn before_save(&self, ctx: &dyn StoreContext, _row_id: u64, value: &mut V) -> Result<(), TableError> {
+        if let Some(pk) = (self.field)(value) {
+            let key = row_key(&pk);
+            if !ctx.kv_store(&self.parent)?.has(&key).map_err(Wrap)? {
+                return Err(ForeignKeyConstraint { foreign_key: self.name.clone(), key: Vec::from(&key[1..]) });
+            }
+        }
+        Ok(())
+    }
+}
+
+// runs before a row is deleted, writing through the `Undo` of the delete so that the writes are
+// rolled back with it
+pub(crate) trait DeleteReferences<K> {
+    fn before_delete(&self, ctx: &dyn StoreContext, key: &K, undo: &mut Undo) -> Result<(), TableError>;
+}
+
+// applies the delete policy to the child rows of a deleted parent row
+struct ReferencedBy<PK, CK, CV> {
+    foreign_key: ForeignKey<PK, CV>,
+    child: Rc<TableImpl<CK, CV>>,
+    on_delete: OnDelete<CV>,
+}
+
+impl<PK, CK, CV> DeleteReferences<PK> for ReferencedBy<PK, CK, CV>
+    where PK: KeyPart + Clone + 'static, CK: KeyPart + 'static, CV: Clone + 'static {
+    fn before_delete(&self, ctx: &dyn StoreContext, key: &PK, undo: &mut Undo) -> Result<(), TableError> {
+        let children = self.foreign_key.index.primary_keys(ctx, &Some(key.clone()))?;
+        for pk in children {
+            let child_key: CK = decode_key(&pk)?;
+            match &self.on_delete {
+                OnDelete::Restrict => return Err(ForeignKeyConstraint {
+                    foreign_key: self.foreign_key.name.clone(),
+                    key: encode_key(key),
+                }),
+                OnDelete::Cascade => self.child.delete_row(ctx, &child_key, undo)?,
+                OnDelete::SetNull(clear) => {
+                    let mut row = match self.child.get_row(ctx, &child_key)? {
+                        None => return Err(TableError::UnexpectedState),
+                        Some(row) => row,
+                    };
+                    clear(&mut row);
+                    if (self.foreign_key.field)(&row).is_some() {
+                        return Err(Other(format!("set-null didn't clear foreign key {}", self.foreign_key.name)));
+                    }
+                    self.child.save_row(ctx, row, undo)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Foreign keys are only checked by `save` and `delete`, not by genesis imports. Cascades and
+/// set-nulls write to the child table before the parent row is deleted, and are rolled back
+/// with the delete if it fails.
+impl<K: KeyPart + 'static, V: 'static> TableImpl<K, V> {
+    /// Declares that `field` references the primary key of `parent`, indexing it under `name`.
+    /// Rows where it is `None` don't reference anything.
+    pub fn add_foreign_key<PK, PV>(&mut self, name: &str, parent: &TableImpl<PK, PV>, field: ReferenceFn<PK, V>) -> ForeignKey<PK, V>
+        where PK: KeyPart + 'static, PV: 'static {
+        let field: FieldFn<PK, V> = Rc::from(field);
+        let index_field = field.clone();
+        let index = self.add_index(name, Box::new(move |v| index_field(v)));
+        self.add_interceptor(Box::new(References {
+            name: String::from(name),
+            parent: parent.store_key.clone(),
+            field: field.clone(),
+        }));
+        ForeignKey { name: String::from(name), field, index }
+    }
+
+    /// Applies `on_delete` to the rows of `child` referencing a row when it is deleted.
+    pub fn add_referencing_table<CK, CV>(&mut self, foreign_key: ForeignKey<K, CV>, child: Rc<TableImpl<CK, CV>>, on_delete: OnDelete<CV>)
+        where K: Clone, CK: KeyPart + 'static, CV: Clone + 'static {
+        self.referenced_by.push(Box::new(ReferencedBy { foreign_key, child, on_delete }));
+    }
+}
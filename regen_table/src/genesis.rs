@@ -0,0 +1,131 @@
+use std::collections::HashSet;
+use std::io::{BufRead, Write};
+use serde_json::{json, Map, Value};
+use crate::{Sequence, StoreContext, TableError, TableImpl};
+use crate::TableError::{Other, Wrap};
+use crate::key::KeyPart;
+use crate::page::read_entries;
+use crate::sequence::encode_val;
+use crate::table::{Undo, row_key, SEQUENCE_PREFIX, ROW_PREFIX, INDEX_PREFIX};
+
+/// Converts rows to and from JSON for genesis import and export. `unmarshal_json` should reject
+/// values which don't match the row schema.
+pub trait JsonMarshaller<T> {
+    fn marshal_json(&self, value: &T) -> Result<Value, TableError>;
+    fn unmarshal_json(&self, json: &Value) -> Result<T, TableError>;
+}
+
+/// Genesis export and import. A table is exported as
+///
+/// ```json
+/// {"rows":[{"row_id":1,"value":...},...],"schema_version":0,"sequence":1}
+/// ```
+///
+/// with rows in primary key order, or as JSON lines with the header fields on the first line
+/// followed by one line per row. Objects have sorted keys so exports are deterministic.
+/// Imports go straight to the store without running interceptors.
+impl<K: KeyPart + 'static, V: Clone + 'static> TableImpl<K, V> {
+    pub fn export_json(&self, ctx: &dyn StoreContext, json: &dyn JsonMarshaller<V>) -> Result<Value, TableError> {
+        let (mut header, rows) = self.export(ctx, json)?;
+        header.insert(String::from("rows"), Value::Array(rows));
+        Ok(Value::Object(header))
+    }
+
+    pub fn export_json_lines(&self, ctx: &dyn StoreContext, json: &dyn JsonMarshaller<V>, out: &mut dyn Write) -> Result<(), TableError> {
+        let (header, rows) = self.export(ctx, json)?;
+        writeln!(out, "{}", Value::Object(header)).map_err(|e| Wrap(Box::new(e)))?;
+        for row in rows {
+            writeln!(out, "{}", row).map_err(|e| Wrap(Box::new(e)))?;
+        }
+        Ok(())
+    }
+
+    fn export(&self, ctx: &dyn StoreContext, json: &dyn JsonMarshaller<V>) -> Result<(Map<String, Value>, Vec<Value>), TableError> {
+        let mut header = Map::new();
+        header.insert(String::from("schema_version"), json!(self.rows.schema_version()));
+        header.insert(String::from("sequence"), json!(self.sequence.cur_val(ctx)?));
+        let mut rows = Vec::new();
+        for (_, bz) in read_entries(ctx, &self.store_key, &vec![ROW_PREFIX], Some(&vec![INDEX_PREFIX]), false, usize::MAX)? {
+            let (row_id, value) = self.rows.decode(&bz)?;
+            rows.push(json!({"row_id": row_id, "value": json.marshal_json(&value)?}));
+        }
+        Ok((header, rows))
+    }
+
+    /// Imports an export into an empty table, checking the rows and rebuilding the indexes.
+    /// Nothing is written if it fails.
+    pub fn import_json(&self, ctx: &dyn StoreContext, json: &dyn JsonMarshaller<V>, genesis: &Value) -> Result<(), TableError> {
+        let rows = match genesis.get("rows") {
+            Some(Value::Array(rows)) => rows,
+            _ => return Err(invalid("missing rows")),
+        };
+        self.import(ctx, json, genesis, rows.iter())
+    }
+
+    pub fn import_json_lines(&self, ctx: &dyn StoreContext, json: &dyn JsonMarshaller<V>, input: &mut dyn BufRead) -> Result<(), TableError> {
+        let mut lines = Vec::new();
+        for line in input.lines() {
+            let line = line.map_err(|e| Wrap(Box::new(e)))?;
+            if !line.trim().is_empty() {
+                lines.push(serde_json::from_str::<Value>(&line).map_err(|e| invalid(&e.to_string()))?);
+            }
+        }
+        match lines.split_first() {
+            None => Err(invalid("missing header")),
+            Some((header, rows)) => self.import(ctx, json, header, rows.iter()),
+        }
+    }
+
+    fn import<'a, I: std::iter::Iterator<Item = &'a Value>>(&self, ctx: &dyn StoreContext, json: &dyn JsonMarshaller<V>, header: &Value, rows: I) -> Result<(), TableError> {
+        let mut undo = Undo::default();
+        let res = self.import_rows(ctx, json, header, rows, &mut undo);
+        undo.finish(ctx, res)
+    }
+
+    fn import_rows<'a, I: std::iter::Iterator<Item = &'a Value>>(&self, ctx: &dyn StoreContext, json: &dyn JsonMarshaller<V>, header: &Value, rows: I, undo: &mut Undo) -> Result<(), TableError> {
+        // other named sequences may share the store
+        if self.sequence.cur_val(ctx)? != 0 || !read_entries(ctx, &self.store_key, &vec![ROW_PREFIX], None, false, 1)?.is_empty() {
+            return Err(Other(String::from("can only import into an empty table")));
+        }
+        let version = field_u64(header, "schema_version")?;
+        if version != self.rows.schema_version() {
+            return Err(invalid(&format!("schema version {} doesn't match the table's {}", version, self.rows.schema_version())));
+        }
+        let sequence = field_u64(header, "sequence")?;
+        let mut row_ids = HashSet::new();
+        let mut keys = HashSet::new();
+        let mut entries = Vec::new();
+        let mut writes = Vec::new();
+        for row in rows {
+            let row_id = field_u64(row, "row_id")?;
+            if row_id == 0 || row_id > sequence || !row_ids.insert(row_id) {
+                return Err(invalid(&format!("invalid row ID {}", row_id)));
+            }
+            let value = json.unmarshal_json(row.get("value").unwrap_or(&Value::Null))?;
+            let key = row_key(&(self.primary_key)(&value));
+            if !keys.insert(key.clone()) {
+                return Err(invalid(&format!("duplicate primary key in row {}", row_id)));
+            }
+            entries.extend(self.index_entries(&key[1..], &value));
+            writes.push((key, self.rows.encode(row_id, &value)?));
+        }
+        self.write_indexes(ctx, entries, undo)?;
+        let mut store = ctx.kv_store(&self.store_key)?;
+        undo.set(&self.store_key, &mut *store, &vec![SEQUENCE_PREFIX], &encode_val(sequence))?;
+        for (key, bz) in writes.iter() {
+            undo.set(&self.store_key, &mut *store, key, bz)?;
+        }
+        Ok(())
+    }
+}
+
+fn invalid(msg: &str) -> TableError {
+    Other(format!("invalid genesis: {}", msg))
+}
+
+fn field_u64(json: &Value, field: &str) -> Result<u64, TableError> {
+    match json.get(field).and_then(Value::as_u64) {
+        None => Err(invalid(&format!("missing {}", field))),
+        Some(n) => Ok(n),
+    }
+}
@@ -0,0 +1,194 @@
+use std::marker::PhantomData;
+use std::rc::Rc;
+use regen_store::Iterator;
+use crate::{Index, UniqueIndex, StoreContext, StoreKey, TableError};
+use crate::page::{Page, PageRequest, RawEntry, read_entries, scan_page};
+use crate::TableError::{NotFound, UnexpectedState, Wrap};
+use crate::key::{KeyPart, encode_key, prefix_end};
+use crate::table::{RowIterator, Rows, ROW_PREFIX, INDEX_PREFIX};
+
+/// An index over the rows of a table, created by `TableImpl::add_index`. Each entry is the
+/// index key followed by the row's primary key, so rows with equal index keys are ordered by
+/// primary key. Entries of unique indexes are just the index key, with the primary key as the
+/// value.
+pub struct SecondaryIndex<IK, V> {
+    store_key: StoreKey,
+    id: u8,
+    unique: bool,
+    rows: Rc<Rows<V>>,
+    _key: PhantomData<IK>,
+}
+
+impl<IK: KeyPart, V> SecondaryIndex<IK, V> {
+    pub(crate) fn new(store_key: StoreKey, id: u8, unique: bool, rows: Rc<Rows<V>>) -> Self {
+        SecondaryIndex { store_key, id, unique, rows, _key: PhantomData }
+    }
+
+    fn get_row(&self, ctx: &dyn StoreContext, pk: &[u8]) -> Result<Vec<u8>, TableError> {
+        let mut row_key = vec![ROW_PREFIX];
+        row_key.extend_from_slice(pk);
+        match ctx.kv_store(&self.store_key)?.get(&row_key).map_err(Wrap)? {
+            // an index entry without a row
+            None => Err(UnexpectedState),
+            Some(bz) => Ok(bz),
+        }
+    }
+
+    fn scan(&self, ctx: &dyn StoreContext, prefix: &[u8], reverse: bool) -> Result<Vec<(IK, V)>, TableError> {
+        let start = self.scan_prefix(prefix);
+        let end = prefix_end(&start);
+        let mut rows = Vec::new();
+        for entry in read_entries(ctx, &self.store_key, &start, end.as_ref(), reverse, usize::MAX)? {
+            if let Some(row) = self.read_entry(ctx, entry)? {
+                rows.push(row);
+            }
+        }
+        Ok(rows)
+    }
+
+    // the encoded primary keys of the rows with index key `key`, without reading the rows
+    pub(crate) fn primary_keys(&self, ctx: &dyn StoreContext, key: &IK) -> Result<Vec<Vec<u8>>, TableError> {
+        let start = encode_key(key);
+        self.primary_keys_in(ctx, &start, prefix_end(&start).as_deref())
+    }
+
+    // the sorted encoded primary keys of the rows with encoded index keys from `start` to `end`
+    pub(crate) fn primary_keys_in(&self, ctx: &dyn StoreContext, start: &[u8], end: Option<&[u8]>) -> Result<Vec<Vec<u8>>, TableError> {
+        if let Some(true) = end.map(|end| end <= start) {
+            return Ok(Vec::new());
+        }
+        let end = match end {
+            Some(end) => Some(self.scan_prefix(end)),
+            None => prefix_end(&[INDEX_PREFIX, self.id]),
+        };
+        let mut pks = Vec::new();
+        for (k, v) in read_entries(ctx, &self.store_key, &self.scan_prefix(start), end.as_ref(), false, usize::MAX)? {
+            if self.unique {
+                pks.push(v);
+            } else {
+                let (_, n) = IK::decode_key(&k[2..])?;
+                pks.push(Vec::from(&k[2 + n..]));
+            }
+        }
+        pks.sort();
+        Ok(pks)
+    }
+
+    // the smallest encoded primary key from `from` on of the rows with encoded index key `key`
+    pub(crate) fn seek(&self, ctx: &dyn StoreContext, key: &[u8], from: &[u8]) -> Result<Option<Vec<u8>>, TableError> {
+        let prefix = self.scan_prefix(key);
+        if self.unique {
+            let pk = ctx.kv_store(&self.store_key)?.get(&prefix).map_err(Wrap)?;
+            return Ok(pk.filter(|pk| &pk[..] >= from));
+        }
+        let mut start = prefix.clone();
+        start.extend_from_slice(from);
+        // encodings are prefix-free, so the entries under `prefix` all have index key `key`
+        let entries = read_entries(ctx, &self.store_key, &start, prefix_end(&prefix).as_ref(), false, 1)?;
+        Ok(entries.into_iter().next().map(|(k, _)| Vec::from(&k[prefix.len()..])))
+    }
+
+    fn scan_page(&self, ctx: &dyn StoreContext, prefix: &[u8], reverse: bool, page: &PageRequest) -> Result<Page<IK, V>, TableError> {
+        scan_page(ctx, &self.store_key, self.scan_prefix(prefix), reverse, page, |entry| self.read_entry(ctx, entry))
+    }
+
+    fn scan_prefix(&self, prefix: &[u8]) -> Vec<u8> {
+        let mut start = vec![INDEX_PREFIX, self.id];
+        start.extend_from_slice(prefix);
+        start
+    }
+
+    fn read_entry(&self, ctx: &dyn StoreContext, (k, v): RawEntry) -> Result<Option<(IK, V)>, TableError> {
+        let (index_key, n) = IK::decode_key(&k[2..])?;
+        let pk = if self.unique { &v[..] } else { &k[2 + n..] };
+        match self.rows.read(ctx, &self.get_row(ctx, pk)?)? {
+            None => Ok(None),
+            Some(value) => Ok(Some((index_key, value))),
+        }
+    }
+}
+
+/// A unique index created by `TableImpl::add_unique_index`.
+pub struct UniqueSecondaryIndex<IK, V>(SecondaryIndex<IK, V>);
+
+impl<IK, V> UniqueSecondaryIndex<IK, V> {
+    pub(crate) fn new(index: SecondaryIndex<IK, V>) -> Self {
+        UniqueSecondaryIndex(index)
+    }
+
+    pub(crate) fn inner(&self) -> &SecondaryIndex<IK, V> {
+        &self.0
+    }
+}
+
+impl<IK: KeyPart + 'static, V: 'static> Index<IK, V> for UniqueSecondaryIndex<IK, V> {
+    fn has(&self, ctx: &dyn StoreContext, key: &IK) -> Result<bool, TableError> {
+        self.0.has(ctx, key)
+    }
+
+    fn get(&self, ctx: &dyn StoreContext, key: &IK) -> Result<Box<dyn Iterator<IK, V>>, TableError> {
+        self.0.get(ctx, key)
+    }
+
+    fn prefix_scan(&self, ctx: &dyn StoreContext, prefix: &[u8]) -> Result<Box<dyn Iterator<IK, V>>, TableError> {
+        self.0.prefix_scan(ctx, prefix)
+    }
+
+    fn reverse_prefix_scan(&self, ctx: &dyn StoreContext, prefix: &[u8]) -> Result<Box<dyn Iterator<IK, V>>, TableError> {
+        self.0.reverse_prefix_scan(ctx, prefix)
+    }
+
+    fn prefix_scan_page(&self, ctx: &dyn StoreContext, prefix: &[u8], page: &PageRequest) -> Result<Page<IK, V>, TableError> {
+        self.0.prefix_scan_page(ctx, prefix, page)
+    }
+
+    fn reverse_prefix_scan_page(&self, ctx: &dyn StoreContext, prefix: &[u8], page: &PageRequest) -> Result<Page<IK, V>, TableError> {
+        self.0.reverse_prefix_scan_page(ctx, prefix, page)
+    }
+}
+
+impl<IK: KeyPart + Clone + 'static, V: 'static> UniqueIndex<IK, V> for UniqueSecondaryIndex<IK, V> {
+    fn get_one(&self, ctx: &dyn StoreContext, key: &IK) -> Result<(IK, V), TableError> {
+        let mut entry = vec![INDEX_PREFIX, self.0.id];
+        key.encode_key(&mut entry);
+        let pk = ctx.kv_store(&self.0.store_key)?.get(&entry).map_err(Wrap)?;
+        match pk {
+            None => Err(NotFound),
+            Some(pk) => match self.0.rows.read(ctx, &self.0.get_row(ctx, &pk)?)? {
+                None => Err(NotFound),
+                Some(value) => Ok((key.clone(), value)),
+            }
+        }
+    }
+}
+
+impl<IK: KeyPart + 'static, V: 'static> Index<IK, V> for SecondaryIndex<IK, V> {
+    fn has(&self, ctx: &dyn StoreContext, key: &IK) -> Result<bool, TableError> {
+        let mut start = vec![INDEX_PREFIX, self.id];
+        key.encode_key(&mut start);
+        let end = prefix_end(&start);
+        let store = ctx.kv_store(&self.store_key)?;
+        let mut it = store.iterator(Some(&start), end.as_ref()).map_err(Wrap)?;
+        Ok(it.next().map_err(Wrap)?.is_some())
+    }
+
+    fn get(&self, ctx: &dyn StoreContext, key: &IK) -> Result<Box<dyn Iterator<IK, V>>, TableError> {
+        self.prefix_scan(ctx, &encode_key(key))
+    }
+
+    fn prefix_scan(&self, ctx: &dyn StoreContext, prefix: &[u8]) -> Result<Box<dyn Iterator<IK, V>>, TableError> {
+        Ok(Box::new(RowIterator(self.scan(ctx, prefix, false)?.into_iter())))
+    }
+
+    fn reverse_prefix_scan(&self, ctx: &dyn StoreContext, prefix: &[u8]) -> Result<Box<dyn Iterator<IK, V>>, TableError> {
+        Ok(Box::new(RowIterator(self.scan(ctx, prefix, true)?.into_iter())))
+    }
+
+    fn prefix_scan_page(&self, ctx: &dyn StoreContext, prefix: &[u8], page: &PageRequest) -> Result<Page<IK, V>, TableError> {
+        self.scan_page(ctx, prefix, false, page)
+    }
+
+    fn reverse_prefix_scan_page(&self, ctx: &dyn StoreContext, prefix: &[u8], page: &PageRequest) -> Result<Page<IK, V>, TableError> {
+        self.scan_page(ctx, prefix, true, page)
+    }
+}
@@ -0,0 +1,234 @@
+use crate::TableError;
+use crate::TableError::InvalidKey;
+
+/// Order-preserving key encoding: for two values `a` and `b` of the same type, `a < b` exactly
+/// when `encode_key(a) < encode_key(b)` byte-wise. Tuples compare element by element, so the
+/// encoding of the first `n` elements of a tuple is a prefix of the full key and can be used
+/// for partial-tuple scans.
+///
+/// * unsigned integers are fixed width big-endian
+/// * signed integers are fixed width big-endian with the sign bit flipped
+/// * `bool` is a single `0` or `1` byte
+/// * `Option` is a `0` byte for `None`, or a `1` byte followed by the value
+/// * bytes and strings have `0x00` escaped as `0x00 0xFF` and are terminated by `0x00 0x01`
+pub trait KeyPart: Sized {
+    fn encode_key(&self, buf: &mut Vec<u8>);
+    /// Decodes a value from the front of `buf`, returning it with the number of bytes read.
+    fn decode_key(buf: &[u8]) -> Result<(Self, usize), TableError>;
+}
+
+pub fn encode_key<T: KeyPart>(key: &T) -> Vec<u8> {
+    let mut buf = Vec::new();
+    key.encode_key(&mut buf);
+    buf
+}
+
+pub fn decode_key<T: KeyPart>(buf: &[u8]) -> Result<T, TableError> {
+    let (key, n) = T::decode_key(buf)?;
+    if n != buf.len() {
+        return Err(InvalidKey(format!("{} trailing bytes", buf.len() - n)));
+    }
+    Ok(key)
+}
+
+/// Builds a key one part at a time, mostly for the prefixes of partial-tuple scans.
+#[derive(Default, Clone)]
+pub struct KeyBuilder(Vec<u8>);
+
+impl KeyBuilder {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn push<T: KeyPart>(mut self, part: &T) -> Self {
+        part.encode_key(&mut self.0);
+        self
+    }
+
+    pub fn build(self) -> Vec<u8> {
+        self.0
+    }
+}
+
+/// Returns the first key after every key starting with `prefix`, or `None` if there is no such
+/// key and a scan should run to the end of the store.
+pub fn prefix_end(prefix: &[u8]) -> Option<Vec<u8>> {
+    let mut end = Vec::from(prefix);
+    while let Some(last) = end.pop() {
+        if last < 0xFF {
+            end.push(last + 1);
+            return Some(end);
+        }
+    }
+    None
+}
+
+fn take<'a>(buf: &'a [u8], n: usize, type_name: &str) -> Result<&'a [u8], TableError> {
+    if buf.len() < n {
+        return Err(InvalidKey(format!("expected {} bytes for {}, got {}", n, type_name, buf.len())));
+    }
+    Ok(&buf[..n])
+}
+
+macro_rules! unsigned_key_part {
+    ($($t:ty),*) => {
+        $(
+            impl KeyPart for $t {
+                fn encode_key(&self, buf: &mut Vec<u8>) {
+                    buf.extend_from_slice(&self.to_be_bytes());
+                }
+
+                fn decode_key(buf: &[u8]) -> Result<(Self, usize), TableError> {
+                    const N: usize = std::mem::size_of::<$t>();
+                    let mut bytes = [0; N];
+                    bytes.copy_from_slice(take(buf, N, stringify!($t))?);
+                    Ok((<$t>::from_be_bytes(bytes), N))
+                }
+            }
+        )*
+    };
+}
+
+unsigned_key_part!(u8, u16, u32, u64, u128);
+
+macro_rules! signed_key_part {
+    ($($t:ty => $u:ty),*) => {
+        $(
+            impl KeyPart for $t {
+                fn encode_key(&self, buf: &mut Vec<u8>) {
+                    // flipping the sign bit puts negative numbers before positive ones
+                    ((*self as $u) ^ (1 << (<$u>::BITS - 1))).encode_key(buf)
+                }
+
+                fn decode_key(buf: &[u8]) -> Result<(Self, usize), TableError> {
+                    let (u, n) = <$u>::decode_key(buf)?;
+                    Ok(((u ^ (1 << (<$u>::BITS - 1))) as $t, n))
+                }
+            }
+        )*
+    };
+}
+
+signed_key_part!(i8 => u8, i16 => u16, i32 => u32, i64 => u64, i128 => u128);
+
+impl KeyPart for bool {
+    fn encode_key(&self, buf: &mut Vec<u8>) {
+        buf.push(*self as u8);
+    }
+
+    fn decode_key(buf: &[u8]) -> Result<(Self, usize), TableError> {
+        match take(buf, 1, "bool")?[0] {
+            0 => Ok((false, 1)),
+            1 => Ok((true, 1)),
+            b => Err(InvalidKey(format!("invalid bool byte {}", b))),
+        }
+    }
+}
+
+impl<T: KeyPart> KeyPart for Option<T> {
+    fn encode_key(&self, buf: &mut Vec<u8>) {
+        match self {
+            None => buf.push(0),
+            Some(value) => {
+                buf.push(1);
+                value.encode_key(buf);
+            }
+        }
+    }
+
+    fn decode_key(buf: &[u8]) -> Result<(Self, usize), TableError> {
+        match take(buf, 1, "Option")?[0] {
+            0 => Ok((None, 1)),
+            1 => {
+                let (value, n) = T::decode_key(&buf[1..])?;
+                Ok((Some(value), n + 1))
+            }
+            b => Err(InvalidKey(format!("invalid Option byte {}", b))),
+        }
+    }
+}
+
+const ESCAPE: u8 = 0x00;
+const ESCAPED_ZERO: u8 = 0xFF;
+const TERMINATOR: u8 = 0x01;
+
+fn encode_bytes(bytes: &[u8], buf: &mut Vec<u8>) {
+    for b in bytes {
+        buf.push(*b);
+        if *b == ESCAPE {
+            buf.push(ESCAPED_ZERO);
+        }
+    }
+    buf.push(ESCAPE);
+    buf.push(TERMINATOR);
+}
+
+fn decode_bytes(buf: &[u8]) -> Result<(Vec<u8>, usize), TableError> {
+    let mut res = Vec::new();
+    let mut i = 0;
+    while i < buf.len() {
+        if buf[i] != ESCAPE {
+            res.push(buf[i]);
+            i += 1;
+            continue;
+        }
+        match buf.get(i + 1) {
+            Some(&ESCAPED_ZERO) => res.push(ESCAPE),
+            Some(&TERMINATOR) => return Ok((res, i + 2)),
+            _ => return Err(InvalidKey(String::from("invalid escape sequence in bytes"))),
+        }
+        i += 2;
+    }
+    Err(InvalidKey(String::from("unterminated bytes")))
+}
+
+impl KeyPart for Vec<u8> {
+    fn encode_key(&self, buf: &mut Vec<u8>) {
+        encode_bytes(self, buf)
+    }
+
+    fn decode_key(buf: &[u8]) -> Result<(Self, usize), TableError> {
+        decode_bytes(buf)
+    }
+}
+
+impl KeyPart for String {
+    fn encode_key(&self, buf: &mut Vec<u8>) {
+        encode_bytes(self.as_bytes(), buf)
+    }
+
+    fn decode_key(buf: &[u8]) -> Result<(Self, usize), TableError> {
+        let (bytes, n) = decode_bytes(buf)?;
+        match String::from_utf8(bytes) {
+            Err(e) => Err(InvalidKey(format!("{}", e))),
+            Ok(s) => Ok((s, n)),
+        }
+    }
+}
+
+macro_rules! tuple_key_part {
+    ($($name:ident),+) => {
+        impl<$($name: KeyPart),+> KeyPart for ($($name,)+) {
+            #[allow(non_snake_case)]
+            fn encode_key(&self, buf: &mut Vec<u8>) {
+                let ($($name,)+) = self;
+                $($name.encode_key(buf);)+
+            }
+
+            fn decode_key(buf: &[u8]) -> Result<(Self, usize), TableError> {
+                let mut n = 0;
+                Ok((($({
+                    let (part, m) = $name::decode_key(&buf[n..])?;
+                    n += m;
+                    part
+                },)+), n))
+            }
+        }
+    };
+}
+
+tuple_key_part!(A);
+tuple_key_part!(A, B);
+tuple_key_part!(A, B, C);
+tuple_key_part!(A, B, C, D);
+tuple_key_part!(A, B, C, D, E);
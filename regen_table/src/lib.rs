@@ -1,9 +1,38 @@
+// the impls generated by err_derive's `Error` derive are wrapped in a const
+#![allow(non_local_definitions)]
+
 use err_derive::Error;
+use std::cell::{RefCell, RefMut};
+use std::collections::HashMap;
 use std::error::Error;
-use regen_store::{Entry, Iterator, ReadonlyKVStore, MutableOrderedMap};
-use regen_context::Context;
+use std::ops::Range;
+use std::rc::Rc;
+use regen_context::{context_key, Context};
+use regen_context::event::{EventManager, EVENTS};
+use regen_store::{Iterator, MutableOrderedMap};
 use crate::TableError::{Other, Wrap};
-use regen_context::ContextError::NotFound;
+
+pub mod key;
+pub mod sequence;
+pub mod table;
+pub mod index;
+pub mod page;
+pub mod genesis;
+pub mod foreign_key;
+pub mod change;
+pub mod row;
+pub mod query;
+
+pub use crate::sequence::SequenceImpl;
+pub use crate::table::TableImpl;
+pub use crate::index::{SecondaryIndex, UniqueSecondaryIndex};
+pub use crate::page::{Page, PageRequest};
+pub use crate::genesis::JsonMarshaller;
+pub use crate::foreign_key::{ForeignKey, OnDelete};
+pub use crate::change::{ChangeEvent, ChangeOp};
+pub use crate::row::TableRow;
+pub use crate::query::{Query, QueryIndex};
+pub use regen_table_derive::TableRow;
 
 #[derive(Debug, Error)]
 pub enum TableError {
@@ -11,76 +40,180 @@ pub enum TableError {
     UnexpectedState,
     #[error(display="not found")]
     NotFound,
+    #[error(display="invalid key: {}", _0)]
+    InvalidKey(String),
+    /// `key` is the encoded index key, see `key::decode_key`.
+    #[error(display="duplicate key {:?} in unique index {}", key, index)]
+    UniqueConstraint{index: String, key: Vec<u8>},
+    /// `key` is the encoded primary key of the referenced row.
+    #[error(display="foreign key {} violated for {:?}", foreign_key, key)]
+    ForeignKeyConstraint{foreign_key: String, key: Vec<u8>},
     #[error(display="{:?}", _0)]
     Other(String),
     #[error(display="{:?}", _0)]
     Wrap(Box<dyn Error>)
 }
 
-pub trait StoreContext: Context {
-    fn readonly_kv_store(&self, key: StoreKey) -> Result<&dyn ReadonlyKVStore<Vec<u8>, Vec<u8>>, TableError>;
-    fn kv_store(&self, key: StoreKey) -> Result<&mut dyn MutableOrderedMap<Vec<u8>, Vec<u8>>, TableError>;
-}
+pub type KVStore<'a> = dyn MutableOrderedMap<Vec<u8>, Vec<u8>> + 'a;
 
-pub struct StoreKey(Vec<u8>);
+pub type KVStoreRef<'a> = RefMut<'a, KVStore<'a>>;
 
-pub trait Index<K, V> {
-    fn has(&self, ctx: &dyn StoreContext, key: &K) -> Result<bool, TableError>;
-    fn get(&self, ctx: &dyn StoreContext, key: &K) -> Result<&dyn Iterator<K, V>, TableError>;
-    fn prefix_scan(&self, ctx: &dyn StoreContext, key: &K) -> Result<&dyn Iterator<K, V>, TableError>;
-    fn reverse_prefix_scan(&self, ctx: &dyn StoreContext, key: &K) -> Result<&dyn Iterator<K, V>, TableError>;
+/// Gives tables access to the store mounted under each `StoreKey`. Stores are borrowed for the
+/// duration of a single table operation, so tables only need `&self` to write.
+pub trait StoreContext {
+    fn kv_store(&self, key: &StoreKey) -> Result<KVStoreRef<'_>, TableError>;
+
+    /// Where tables emit their `ChangeEvent`s, `None` to drop them.
+    fn events(&self) -> Option<&EventManager> {
+        None
+    }
 }
 
-pub trait UniqueIndex<K, V>: Index<K, V> {
-    fn get_one(&self, ctx: &dyn StoreContext, key: &K) -> Result<&dyn Entry<K, V>, TableError>;
+context_key!(
+    /// Where a `regen_context::Context` carries the stores of the current ABCI phase.
+    pub STORE_CONTEXT: Rc<dyn StoreContext>
+);
+
+/// Gets the `StoreContext` carried by `ctx`, so that handlers taking `&dyn Context` can use
+/// tables. Table changes go to the `EventManager` of `ctx` if it has one, so the change events
+/// of a sub-execution with its own events are discarded along with them, its writes are not.
+pub fn store_context(ctx: &dyn Context) -> Result<ContextStores<'_>, TableError> {
+    match STORE_CONTEXT.get(ctx) {
+        Err(e) => Err(Wrap(Box::new(e))),
+        Ok(stores) => Ok(ContextStores { stores: stores.as_ref(), events: EVENTS.get(ctx).ok() }),
+    }
 }
 
-pub trait Table<K, V>: UniqueIndex<K, V> {
-    fn delete(&self, ctx: &dyn StoreContext, k: &K) -> Option<TableError>;
-    fn save(&self, ctx: &dyn StoreContext, v: &V) -> Result<Option<&V>, TableError>;
+/// The `StoreContext` of a `Context` with its events, see `store_context`.
+pub struct ContextStores<'a> {
+    stores: &'a dyn StoreContext,
+    events: Option<&'a EventManager>,
 }
 
-pub trait TableInterceptor<K, V> {
-    fn on_read(&self, ctx: &dyn StoreContext, value: &V) -> Result<Option<&V>, TableError>;
-    fn before_save(&self, ctx: &dyn StoreContext, row_id: u64, value: &mut V) -> Result<(), TableError>;
-    fn after_save(&self, ctx: &dyn StoreContext, row_id: u64, value: &V) -> Result<(), TableError>;
-    fn before_delete(&self, ctx: &dyn StoreContext, row_id: u64, key: &K) -> Result<(), TableError>;
-    fn after_delete(&self, ctx: &dyn StoreContext, row_id: u64, key: &K) -> Result<(), TableError>;
+impl<'a> StoreContext for ContextStores<'a> {
+    fn kv_store(&self, key: &StoreKey) -> Result<KVStoreRef<'_>, TableError> {
+        Ok(RefMut::map(self.stores.kv_store(key)?, |s| s as &mut KVStore))
+    }
+
+    fn events(&self) -> Option<&EventManager> {
+        self.events.or_else(|| self.stores.events())
+    }
 }
 
-pub trait Sequence {
-    fn next_val(&self, ctx: &dyn StoreContext) -> u64;
-    fn cur_val(&self, ctx: &dyn StoreContext) -> u64;
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct StoreKey(pub Vec<u8>);
+
+impl StoreKey {
+    pub fn new(name: &str) -> Self {
+        StoreKey(Vec::from(name.as_bytes()))
+    }
 }
 
-struct SequenceImpl(StoreKey);
+/// A `StoreContext` over stores mounted by the caller, for instance the batches of the current
+/// transaction.
+#[derive(Default)]
+pub struct SimpleStoreContext<'a> {
+    stores: HashMap<StoreKey, RefCell<&'a mut KVStore<'a>>>,
+    events: Option<&'a EventManager>,
+}
 
-const SEQ_KEY: Vec<u8> = Vec::from([0; 0]);
+impl<'a> SimpleStoreContext<'a> {
+    pub fn new() -> Self {
+        Default::default()
+    }
 
-impl Sequence for SequenceImpl {
-    fn next_val(&self, ctx: &dyn StoreContext) -> Result<u64, TableError> {
-        let cur = self.cur_val(ctx)?;
-        let store = ctx.kv_store(self.0)?;
-        let next = cur + 1;
-        let mut buf = unsigned_varint::encode::u64_buffer();
-        let res = unsigned_varint::encode::u64(next, &mut buf);
-        match store.set(&SEQ_KEY, &Vec::from(res)) {
-            Err(e) => Err(Wrap(Box::from(e))),
-            _ => Ok(next)
+    pub fn mount(&mut self, key: StoreKey, store: &'a mut KVStore<'a>) -> Result<(), TableError> {
+        if self.stores.contains_key(&key) {
+            return Err(Other(format!("store {:?} is already mounted", key)));
         }
+        self.stores.insert(key, RefCell::new(store));
+        Ok(())
     }
 
-    fn cur_val(&self, ctx: &dyn StoreContext) -> Result<u64, TableError> {
-        let store = ctx.readonly_kv_store(self.0)?;
-        match store.get(&SEQ_KEY) {
-            Err(e) => Err(Wrap(Box::from(e))),
-            Ok(None) => Ok(0),
-            Ok(Some(r)) =>{
-                match unsigned_varint::decode::u64(r) {
-                    Err(e) => Err(Wrap(Box::from(e))),
-                    x => x
-                }
+    pub fn set_events(&mut self, events: &'a EventManager) {
+        self.events = Some(events);
+    }
+}
+
+impl<'a> StoreContext for SimpleStoreContext<'a> {
+    fn kv_store(&self, key: &StoreKey) -> Result<KVStoreRef<'_>, TableError> {
+        match self.stores.get(key) {
+            None => Err(Other(format!("store {:?} is not mounted", key))),
+            Some(store) => match store.try_borrow_mut() {
+                Err(e) => Err(Wrap(Box::from(e))),
+                Ok(store) => Ok(RefMut::map(store, |s| &mut **s as &mut KVStore)),
             }
         }
     }
+
+    fn events(&self) -> Option<&EventManager> {
+        self.events
+    }
+}
+
+/// Converts rows to and from the bytes stored in a table.
+pub trait Marshaller<T> {
+    fn marshal(&self, value: &T) -> Result<Vec<u8>, TableError>;
+    fn unmarshal(&self, bz: &[u8]) -> Result<T, TableError>;
+}
+
+/// Index and table iterators are read eagerly since the store they come from is only borrowed
+/// while the scan runs.
+pub trait Index<K, V> {
+    fn has(&self, ctx: &dyn StoreContext, key: &K) -> Result<bool, TableError>;
+    fn get(&self, ctx: &dyn StoreContext, key: &K) -> Result<Box<dyn Iterator<K, V>>, TableError>;
+    /// Scans the keys starting with `prefix`, which is the encoding of the leading parts of a
+    /// key (see `key::KeyBuilder`).
+    fn prefix_scan(&self, ctx: &dyn StoreContext, prefix: &[u8]) -> Result<Box<dyn Iterator<K, V>>, TableError>;
+    fn reverse_prefix_scan(&self, ctx: &dyn StoreContext, prefix: &[u8]) -> Result<Box<dyn Iterator<K, V>>, TableError>;
+    /// Like `prefix_scan`, but only reads the next `page.limit` entries after `page.cursor`.
+    fn prefix_scan_page(&self, ctx: &dyn StoreContext, prefix: &[u8], page: &PageRequest) -> Result<Page<K, V>, TableError>;
+    fn reverse_prefix_scan_page(&self, ctx: &dyn StoreContext, prefix: &[u8], page: &PageRequest) -> Result<Page<K, V>, TableError>;
+}
+
+pub trait UniqueIndex<K, V>: Index<K, V> {
+    /// Returns `TableError::NotFound` if there is no entry for `key`.
+    fn get_one(&self, ctx: &dyn StoreContext, key: &K) -> Result<(K, V), TableError>;
+}
+
+pub trait Table<K, V>: UniqueIndex<K, V> {
+    fn delete(&self, ctx: &dyn StoreContext, k: &K) -> Result<(), TableError>;
+    /// Inserts or updates the row with the primary key of `v`, returning its row ID.
+    fn save(&self, ctx: &dyn StoreContext, v: &V) -> Result<u64, TableError>;
+}
+
+/// Hooks around table operations, see `TableImpl::add_interceptor`. An error from any hook
+/// aborts the operation with nothing written.
+pub trait TableInterceptor<K, V> {
+    /// Can replace the row being read, or hide it by returning `None`.
+    fn on_read(&self, _ctx: &dyn StoreContext, value: V) -> Result<Option<V>, TableError> {
+        Ok(Some(value))
+    }
+
+    /// Can modify the row before it is written, except for its primary key.
+    fn before_save(&self, _ctx: &dyn StoreContext, _row_id: u64, _value: &mut V) -> Result<(), TableError> {
+        Ok(())
+    }
+
+    fn after_save(&self, _ctx: &dyn StoreContext, _row_id: u64, _value: &V) -> Result<(), TableError> {
+        Ok(())
+    }
+
+    fn before_delete(&self, _ctx: &dyn StoreContext, _row_id: u64, _key: &K) -> Result<(), TableError> {
+        Ok(())
+    }
+
+    fn after_delete(&self, _ctx: &dyn StoreContext, _row_id: u64, _key: &K) -> Result<(), TableError> {
+        Ok(())
+    }
+}
+
+pub trait Sequence {
+    fn next_val(&self, ctx: &dyn StoreContext) -> Result<u64, TableError>;
+    fn cur_val(&self, ctx: &dyn StoreContext) -> Result<u64, TableError>;
+    /// Reserves the next `n` values with a single write, returning them as a range.
+    fn reserve(&self, ctx: &dyn StoreContext, n: u64) -> Result<Range<u64>, TableError>;
+    /// Moves the sequence forward so that the next value is `val + 1`, for instance after
+    /// importing rows with existing IDs. Fails if that would hand out a value twice.
+    fn set_val(&self, ctx: &dyn StoreContext, val: u64) -> Result<(), TableError>;
 }
@@ -0,0 +1,98 @@
+use crate::{StoreContext, StoreKey, TableError};
+use crate::TableError::{InvalidKey, Other, Wrap};
+use crate::key::prefix_end;
+
+/// Asks for the next `limit` entries of a scan, see `Index::prefix_scan_page`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PageRequest {
+    pub limit: usize,
+    /// The `next_cursor` of the previous page, or `None` for the first page.
+    pub cursor: Option<Vec<u8>>,
+    /// Also counts every entry of the scan. Stores which override `OrderedMap::count`, like
+    /// regen_avl's, do this without scanning.
+    pub count_total: bool,
+}
+
+#[derive(Debug)]
+pub struct Page<K, V> {
+    pub items: Vec<(K, V)>,
+    /// An opaque cursor to pass in the next `PageRequest`, `None` on the last page.
+    pub next_cursor: Option<Vec<u8>>,
+    /// Set if `count_total` was requested. Rows hidden by `on_read` hooks are included.
+    pub total: Option<u64>,
+}
+
+pub(crate) type RawEntry = (Vec<u8>, Vec<u8>);
+
+// reads up to `limit` entries of a range, releasing the store before returning them
+pub(crate) fn read_entries(ctx: &dyn StoreContext, store_key: &StoreKey, start: &Vec<u8>, end: Option<&Vec<u8>>, reverse: bool, limit: usize) -> Result<Vec<RawEntry>, TableError> {
+    let store = ctx.kv_store(store_key)?;
+    let mut it = if reverse {
+        store.reverse_iterator(Some(start), end)
+    } else {
+        store.iterator(Some(start), end)
+    }.map_err(Wrap)?;
+    let mut entries = Vec::new();
+    while entries.len() < limit {
+        match it.next().map_err(Wrap)? {
+            None => break,
+            Some(entry) => entries.push(entry),
+        }
+    }
+    Ok(entries)
+}
+
+// Pages through the store keys starting with `prefix`. The cursor is the store key of the last
+// entry read, which may not have been returned if `decode` hid it.
+pub(crate) fn scan_page<K, V, F>(ctx: &dyn StoreContext, store_key: &StoreKey, prefix: Vec<u8>, reverse: bool, req: &PageRequest, mut decode: F) -> Result<Page<K, V>, TableError>
+    where F: FnMut(RawEntry) -> Result<Option<(K, V)>, TableError> {
+    if req.limit == 0 {
+        return Err(Other(String::from("page limit must be positive")));
+    }
+    let mut end = prefix_end(&prefix);
+    let total = if req.count_total {
+        Some(ctx.kv_store(store_key)?.count(Some(&prefix), end.as_ref()).map_err(Wrap)?)
+    } else {
+        None
+    };
+    let mut start = prefix.clone();
+    if let Some(cursor) = &req.cursor {
+        if !cursor.starts_with(&prefix) {
+            return Err(InvalidKey(String::from("cursor is not from this scan")));
+        }
+        seek_past(&mut start, &mut end, cursor, reverse);
+    }
+    let mut items = Vec::new();
+    loop {
+        let wanted = req.limit - items.len();
+        // one extra entry tells whether there is another page
+        let mut entries = read_entries(ctx, store_key, &start, end.as_ref(), reverse, wanted + 1)?;
+        let more = entries.len() > wanted;
+        entries.truncate(wanted);
+        let last = entries.last().map(|(k, _)| k.clone());
+        for entry in entries {
+            if let Some(item) = decode(entry)? {
+                items.push(item);
+            }
+        }
+        match last {
+            Some(last) if more => {
+                if items.len() == req.limit {
+                    return Ok(Page { items, next_cursor: Some(last), total });
+                }
+                seek_past(&mut start, &mut end, &last, reverse);
+            }
+            _ => return Ok(Page { items, next_cursor: None, total }),
+        }
+    }
+}
+
+fn seek_past(start: &mut Vec<u8>, end: &mut Option<Vec<u8>>, key: &[u8], reverse: bool) {
+    if reverse {
+        *end = Some(Vec::from(key));
+    } else {
+        // the smallest key after `key`
+        *start = Vec::from(key);
+        start.push(0);
+    }
+}
@@ -0,0 +1,190 @@
+use std::ops::{Bound, RangeBounds};
+use crate::{StoreContext, TableError, TableImpl, SecondaryIndex, UniqueSecondaryIndex, Page, PageRequest};
+use crate::TableError::{Other, UnexpectedState};
+use crate::key::{KeyPart, encode_key, decode_key, prefix_end};
+use crate::page::RawEntry;
+use crate::table::ROW_PREFIX;
+
+/// An index which can filter a `Query`.
+pub trait QueryIndex<IK, V> {
+    /// The smallest encoded primary key from `from` on of the rows with the encoded index key
+    /// `key`.
+    fn seek(&self, ctx: &dyn StoreContext, key: &[u8], from: &[u8]) -> Result<Option<Vec<u8>>, TableError>;
+    /// The sorted encoded primary keys of the rows with encoded index keys from `start` to
+    /// `end`, exclusive.
+    fn primary_keys_in(&self, ctx: &dyn StoreContext, start: &[u8], end: Option<&[u8]>) -> Result<Vec<Vec<u8>>, TableError>;
+}
+
+impl<IK: KeyPart, V> QueryIndex<IK, V> for SecondaryIndex<IK, V> {
+    fn seek(&self, ctx: &dyn StoreContext, key: &[u8], from: &[u8]) -> Result<Option<Vec<u8>>, TableError> {
+        SecondaryIndex::seek(self, ctx, key, from)
+    }
+
+    fn primary_keys_in(&self, ctx: &dyn StoreContext, start: &[u8], end: Option<&[u8]>) -> Result<Vec<Vec<u8>>, TableError> {
+        SecondaryIndex::primary_keys_in(self, ctx, start, end)
+    }
+}
+
+impl<IK: KeyPart, V> QueryIndex<IK, V> for UniqueSecondaryIndex<IK, V> {
+    fn seek(&self, ctx: &dyn StoreContext, key: &[u8], from: &[u8]) -> Result<Option<Vec<u8>>, TableError> {
+        self.inner().seek(ctx, key, from)
+    }
+
+    fn primary_keys_in(&self, ctx: &dyn StoreContext, start: &[u8], end: Option<&[u8]>) -> Result<Vec<Vec<u8>>, TableError> {
+        self.inner().primary_keys_in(ctx, start, end)
+    }
+}
+
+type SeekFn<'a> = Box<dyn Fn(&dyn StoreContext, &[u8]) -> Result<Option<Vec<u8>>, TableError> + 'a>;
+
+type RangeFn<'a> = Box<dyn Fn(&dyn StoreContext) -> Result<Vec<Vec<u8>>, TableError> + 'a>;
+
+enum Condition<'a> {
+    Eq(SeekFn<'a>),
+    Range(RangeFn<'a>),
+}
+
+// a condition while a page is read, ranges hold the primary keys read from their index
+enum Cursor<'q, 'a> {
+    Eq(&'q SeekFn<'a>),
+    Range(Vec<Vec<u8>>),
+}
+
+impl<'q, 'a> Cursor<'q, 'a> {
+    // the smallest primary key from `from` on matching the condition
+    fn seek(&self, ctx: &dyn StoreContext, from: &[u8]) -> Result<Option<Vec<u8>>, TableError> {
+        match self {
+            Cursor::Eq(seek) => seek(ctx, from),
+            Cursor::Range(pks) => Ok(pks.get(pks.partition_point(|pk| &pk[..] < from)).cloned()),
+        }
+    }
+}
+
+/// Finds the rows of a table matching every condition on its indexes, in primary key order.
+/// The primary keys matching an `eq` condition are read in order from its index, the ones
+/// matching a `range` condition are read from the index entries in the range once per page,
+/// and the conditions are intersected by skipping ahead to the largest key seen so far. Only
+/// the matching index entries and rows are read. Created by `TableImpl::query`, the indexes
+/// must belong to the same table.
+pub struct Query<'a, K, V> {
+    table: &'a TableImpl<K, V>,
+    conditions: Vec<Condition<'a>>,
+}
+
+impl<K: KeyPart + 'static, V: 'static> TableImpl<K, V> {
+    pub fn query(&self) -> Query<'_, K, V> {
+        Query { table: self, conditions: Vec::new() }
+    }
+}
+
+// the smallest key after `key`
+fn successor(key: &[u8]) -> Vec<u8> {
+    let mut next = Vec::from(key);
+    next.push(0);
+    next
+}
+
+impl<'a, K: KeyPart + 'static, V: 'static> Query<'a, K, V> {
+    /// Matches rows with index key `key`.
+    pub fn eq<IK: KeyPart>(mut self, index: &'a dyn QueryIndex<IK, V>, key: &IK) -> Self {
+        let key = encode_key(key);
+        self.conditions.push(Condition::Eq(Box::new(move |ctx, from| index.seek(ctx, &key, from))));
+        self
+    }
+
+    /// Matches rows with index keys in `range`, for instance `2023..2026` or `2023..=2025`.
+    pub fn range<IK: KeyPart, R: RangeBounds<IK>>(mut self, index: &'a dyn QueryIndex<IK, V>, range: R) -> Self {
+        // encodings are prefix-free, so the keys after `k` start at `prefix_end(encode_key(k))`
+        let start = match range.start_bound() {
+            Bound::Included(k) => Some(encode_key(k)),
+            Bound::Excluded(k) => prefix_end(&encode_key(k)),
+            Bound::Unbounded => Some(Vec::new()),
+        };
+        let end = match range.end_bound() {
+            Bound::Included(k) => prefix_end(&encode_key(k)),
+            Bound::Excluded(k) => Some(encode_key(k)),
+            Bound::Unbounded => None,
+        };
+        self.conditions.push(Condition::Range(Box::new(move |ctx| match &start {
+            // nothing comes after the start key
+            None => Ok(Vec::new()),
+            Some(start) => index.primary_keys_in(ctx, start, end.as_deref()),
+        })));
+        self
+    }
+
+    /// Reads a page of the matching rows. The cursor is the encoded primary key of the last
+    /// row read. A query needs at least one condition. Counting the total reads every match.
+    pub fn page(&self, ctx: &dyn StoreContext, req: &PageRequest) -> Result<Page<K, V>, TableError> {
+        if req.limit == 0 {
+            return Err(Other(String::from("page limit must be positive")));
+        }
+        let total = if req.count_total { Some(self.count(ctx)?) } else { None };
+        let cursors = self.cursors(ctx)?;
+        let mut from = req.cursor.as_deref().map_or_else(Vec::new, successor);
+        let mut items = Vec::new();
+        let mut last = None;
+        while let Some((pk, bz)) = self.next_match(ctx, &cursors, from)? {
+            if items.len() == req.limit {
+                return Ok(Page { items, next_cursor: last, total });
+            }
+            if let Some(value) = self.table.rows.read(ctx, &bz)? {
+                items.push((decode_key(&pk)?, value));
+            }
+            from = successor(&pk);
+            last = Some(pk);
+        }
+        Ok(Page { items, next_cursor: None, total })
+    }
+
+    // counts the matching rows, including the ones hidden by `on_read` hooks
+    fn count(&self, ctx: &dyn StoreContext) -> Result<u64, TableError> {
+        let cursors = self.cursors(ctx)?;
+        let mut n = 0;
+        let mut from = Vec::new();
+        while let Some((pk, _)) = self.next_match(ctx, &cursors, from)? {
+            n += 1;
+            from = successor(&pk);
+        }
+        Ok(n)
+    }
+
+    fn cursors(&self, ctx: &dyn StoreContext) -> Result<Vec<Cursor<'_, 'a>>, TableError> {
+        if self.conditions.is_empty() {
+            return Err(Other(String::from("query has no conditions")));
+        }
+        self.conditions.iter().map(|condition| match condition {
+            Condition::Eq(seek) => Ok(Cursor::Eq(seek)),
+            Condition::Range(primary_keys) => Ok(Cursor::Range(primary_keys(ctx)?)),
+        }).collect()
+    }
+
+    // the primary key and stored row of the first row from `from` on matching every condition
+    fn next_match(&self, ctx: &dyn StoreContext, cursors: &[Cursor], mut from: Vec<u8>) -> Result<Option<RawEntry>, TableError> {
+        let mut agreed = 0;
+        let mut i = 0;
+        // each condition moves `from` up to its next match until they all agree on it
+        while agreed < cursors.len() {
+            match cursors[i].seek(ctx, &from)? {
+                None => return Ok(None),
+                Some(pk) if pk == from => agreed += 1,
+                Some(pk) => {
+                    from = pk;
+                    agreed = 1;
+                }
+            }
+            i = (i + 1) % cursors.len();
+        }
+        match self.table.get_raw(ctx, &row_key_of(&from))? {
+            // an index entry without a row
+            None => Err(UnexpectedState),
+            Some(bz) => Ok(Some((from, bz))),
+        }
+    }
+}
+
+fn row_key_of(pk: &[u8]) -> Vec<u8> {
+    let mut key = vec![ROW_PREFIX];
+    key.extend_from_slice(pk);
+    key
+}
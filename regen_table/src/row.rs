@@ -0,0 +1,35 @@
+use crate::{Marshaller, StoreKey, TableImpl};
+use crate::key::KeyPart;
+
+/// A row type which knows its primary key and indexes, usually derived with
+/// `#[derive(TableRow)]` on a struct with named fields:
+///
+/// ```ignore
+/// #[derive(Clone, TableRow)]
+/// struct Account {
+///     #[primary_key]
+///     owner: String,
+///     #[index]
+///     denom: String,
+///     #[unique]
+///     number: u32,
+///     balance: u64,
+/// }
+/// ```
+///
+/// The primary key is the `#[primary_key]` field, or a tuple of them in declaration order.
+/// Each `#[index]` or `#[unique]` field gets an index named after it, added in declaration
+/// order, and a field of the same name in the generated `AccountIndexes`. The derive also adds
+/// `Account::encode_primary_key` and `Account::encode_<field>_key` functions encoding keys the
+/// way they are stored, so indexed fields can't be named `primary`. Key fields must implement
+/// `KeyPart` and `Clone`.
+pub trait TableRow: Sized + 'static {
+    type PrimaryKey: KeyPart + 'static;
+    /// The typed accessors of the indexes added by `table`.
+    type Indexes;
+
+    fn primary_key(&self) -> Self::PrimaryKey;
+
+    /// Creates the table of these rows with its indexes.
+    fn table(store_key: StoreKey, marshaller: Box<dyn Marshaller<Self>>) -> (TableImpl<Self::PrimaryKey, Self>, Self::Indexes);
+}
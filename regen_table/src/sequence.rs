@@ -0,0 +1,72 @@
+use std::ops::Range;
+use crate::{Sequence, StoreContext, StoreKey, TableError};
+use crate::TableError::{Other, Wrap};
+use crate::table::SEQUENCE_PREFIX;
+
+/// A counter stored under `key` in the store for `StoreKey`, starting at 0 so the first
+/// `next_val` is 1.
+pub struct SequenceImpl(StoreKey, Vec<u8>);
+
+impl SequenceImpl {
+    pub fn new(store_key: StoreKey, key: Vec<u8>) -> Self {
+        SequenceImpl(store_key, key)
+    }
+
+    /// A sequence stored next to the row ID sequence of a table using the same store, which is
+    /// the one named `""`. Sequences with different names are independent.
+    pub fn named(store_key: StoreKey, name: &str) -> Self {
+        let mut key = vec![SEQUENCE_PREFIX];
+        key.extend_from_slice(name.as_bytes());
+        SequenceImpl(store_key, key)
+    }
+
+    fn write(&self, ctx: &dyn StoreContext, val: u64) -> Result<(), TableError> {
+        let mut store = ctx.kv_store(&self.0)?;
+        store.set(&self.1, &encode_val(val)).map_err(Wrap)
+    }
+}
+
+impl Sequence for SequenceImpl {
+    fn next_val(&self, ctx: &dyn StoreContext) -> Result<u64, TableError> {
+        Ok(self.reserve(ctx, 1)?.start)
+    }
+
+    fn cur_val(&self, ctx: &dyn StoreContext) -> Result<u64, TableError> {
+        let store = ctx.kv_store(&self.0)?;
+        match store.get(&self.1) {
+            Err(e) => Err(Wrap(e)),
+            Ok(None) => Ok(0),
+            Ok(Some(r)) => {
+                match unsigned_varint::decode::u64(&r) {
+                    Err(e) => Err(Wrap(Box::from(format!("{:?}", e)))),
+                    Ok((x, _)) => Ok(x)
+                }
+            }
+        }
+    }
+
+    fn reserve(&self, ctx: &dyn StoreContext, n: u64) -> Result<Range<u64>, TableError> {
+        let cur = self.cur_val(ctx)?;
+        // the end of the range must fit in a u64 too
+        match cur.checked_add(n).filter(|last| *last < u64::MAX) {
+            None => Err(Other(String::from("sequence overflow"))),
+            Some(last) => {
+                self.write(ctx, last)?;
+                Ok(cur + 1..last + 1)
+            }
+        }
+    }
+
+    fn set_val(&self, ctx: &dyn StoreContext, val: u64) -> Result<(), TableError> {
+        let cur = self.cur_val(ctx)?;
+        if val < cur {
+            return Err(Other(format!("can't move sequence back from {} to {}", cur, val)));
+        }
+        self.write(ctx, val)
+    }
+}
+
+pub(crate) fn encode_val(val: u64) -> Vec<u8> {
+    let mut buf = unsigned_varint::encode::u64_buffer();
+    Vec::from(unsigned_varint::encode::u64(val, &mut buf))
+}
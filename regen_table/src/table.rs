@@ -0,0 +1,498 @@
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::convert::TryInto;
+use std::rc::Rc;
+use regen_store::Iterator;
+use crate::{Index, UniqueIndex, Table, TableInterceptor, Sequence, SequenceImpl, StoreContext, StoreKey, KVStore, Marshaller, TableError};
+use crate::TableError::{NotFound, Other, UnexpectedState, UniqueConstraint, Wrap};
+use crate::key::{KeyPart, encode_key, decode_key, prefix_end};
+use crate::index::{SecondaryIndex, UniqueSecondaryIndex};
+use crate::change::{ChangeEmitter, ChangeEvent, ChangedFieldsFn};
+use crate::foreign_key::DeleteReferences;
+use crate::page::{Page, PageRequest, RawEntry, read_entries, scan_page};
+
+// layout of a table's store
+pub(crate) const SEQUENCE_PREFIX: u8 = 0;
+pub(crate) const ROW_PREFIX: u8 = 1;
+pub(crate) const INDEX_PREFIX: u8 = 2;
+
+/// A table storing each row under its primary key in the store for `StoreKey`. Rows are given
+/// an auto-incrementing row ID when they are first saved, which they keep until deleted.
+pub struct TableImpl<K, V> {
+    pub(crate) store_key: StoreKey,
+    pub(crate) rows: Rc<Rows<V>>,
+    pub(crate) primary_key: Box<dyn Fn(&V) -> K>,
+    pub(crate) sequence: SequenceImpl,
+    indexes: Vec<IndexDef<V>>,
+    interceptors: Vec<Rc<dyn TableInterceptor<K, V>>>,
+    pub(crate) referenced_by: Vec<Box<dyn DeleteReferences<K>>>,
+    changes: Option<ChangeEmitter<V>>,
+}
+
+type ReadHook<V> = Box<dyn Fn(&dyn StoreContext, V) -> Result<Option<V>, TableError>>;
+
+/// Upgrades a marshalled row from one schema version to the next, see `TableImpl::add_migration`.
+pub type Migration = Box<dyn Fn(Vec<u8>) -> Result<Vec<u8>, TableError>>;
+
+/// Decodes rows for a table and its indexes, upgrading them to the current schema version and
+/// running the `on_read` hooks.
+pub(crate) struct Rows<V> {
+    marshaller: Box<dyn Marshaller<V>>,
+    on_read: RefCell<Vec<ReadHook<V>>>,
+    migrations: RefCell<Vec<Migration>>,
+}
+
+impl<V> Rows<V> {
+    pub(crate) fn schema_version(&self) -> u64 {
+        self.migrations.borrow().len() as u64
+    }
+
+    pub(crate) fn encode(&self, row_id: u64, value: &V) -> Result<Vec<u8>, TableError> {
+        let mut bz = row_id.to_be_bytes().to_vec();
+        let mut buf = unsigned_varint::encode::u64_buffer();
+        bz.extend_from_slice(unsigned_varint::encode::u64(self.schema_version(), &mut buf));
+        bz.extend(self.marshaller.marshal(value)?);
+        Ok(bz)
+    }
+
+    pub(crate) fn decode(&self, bz: &[u8]) -> Result<(u64, V), TableError> {
+        let (row_id, version, value) = split_row(bz)?;
+        let migrations = self.migrations.borrow();
+        if version > migrations.len() as u64 {
+            return Err(Other(format!("row {} has schema version {} but the table is at {}", row_id, version, migrations.len())));
+        }
+        let mut value = Vec::from(value);
+        for migration in migrations[version as usize..].iter() {
+            value = migration(value)?;
+        }
+        Ok((row_id, self.marshaller.unmarshal(&value)?))
+    }
+
+    // hooks may read tables themselves, so the store must not be borrowed while they run
+    pub(crate) fn read(&self, ctx: &dyn StoreContext, bz: &[u8]) -> Result<Option<V>, TableError> {
+        let (_, mut value) = self.decode(bz)?;
+        for hook in self.on_read.borrow().iter() {
+            match hook(ctx, value)? {
+                None => return Ok(None),
+                Some(v) => value = v,
+            }
+        }
+        Ok(Some(value))
+    }
+}
+
+// the previous values of the keys an operation wrote, so that it can be rolled back when a
+// hook fails, including the writes to other tables made by foreign key cascades, and the
+// change events of the rows it wrote, which are only emitted once it succeeds
+#[derive(Default)]
+pub(crate) struct Undo {
+    writes: Vec<(StoreKey, Vec<u8>, Option<Vec<u8>>)>,
+    changes: Vec<ChangeEvent>,
+}
+
+impl Undo {
+    fn record(&mut self, store_key: &StoreKey, store: &KVStore, key: &[u8]) -> Result<(), TableError> {
+        let key = Vec::from(key);
+        let prev = store.get(&key).map_err(Wrap)?;
+        self.writes.push((store_key.clone(), key, prev));
+        Ok(())
+    }
+
+    pub(crate) fn set(&mut self, store_key: &StoreKey, store: &mut KVStore, key: &Vec<u8>, value: &Vec<u8>) -> Result<(), TableError> {
+        self.record(store_key, store, key)?;
+        store.set(key, value).map_err(Wrap)
+    }
+
+    fn delete(&mut self, store_key: &StoreKey, store: &mut KVStore, key: &Vec<u8>) -> Result<(), TableError> {
+        self.record(store_key, store, key)?;
+        store.delete(key).map_err(Wrap)
+    }
+
+    pub(crate) fn emit(&mut self, change: ChangeEvent) {
+        self.changes.push(change);
+    }
+
+    // emits the buffered changes if `res` is ok, otherwise rolls back the writes and drops them
+    pub(crate) fn finish<T>(self, ctx: &dyn StoreContext, res: Result<T, TableError>) -> Result<T, TableError> {
+        if res.is_err() {
+            self.rollback(ctx)?;
+        } else if let Some(events) = ctx.events() {
+            for change in self.changes.iter() {
+                events.emit_typed(change);
+            }
+        }
+        res
+    }
+
+    fn rollback(self, ctx: &dyn StoreContext) -> Result<(), TableError> {
+        for (store_key, key, prev) in self.writes.into_iter().rev() {
+            let mut store = ctx.kv_store(&store_key)?;
+            match prev {
+                None => store.delete(&key),
+                Some(v) => store.set(&key, &v),
+            }.map_err(Wrap)?;
+        }
+        Ok(())
+    }
+}
+
+// returns the encoded index key of a row
+type IndexKeyFn<V> = Box<dyn Fn(&V) -> Vec<u8>>;
+
+struct IndexDef<V> {
+    name: String,
+    unique: bool,
+    key: IndexKeyFn<V>,
+}
+
+// an index entry, unique index entries store the primary key as the value so that it can be
+// found with a single get
+pub(crate) struct IndexEntry {
+    index: usize,
+    key: Vec<u8>,
+    value: Vec<u8>,
+}
+
+impl PartialEq for IndexEntry {
+    fn eq(&self, other: &Self) -> bool {
+        self.key == other.key && self.value == other.value
+    }
+}
+
+impl<K: KeyPart + 'static, V: 'static> TableImpl<K, V> {
+    pub fn new(store_key: StoreKey, marshaller: Box<dyn Marshaller<V>>, primary_key: Box<dyn Fn(&V) -> K>) -> Self {
+        TableImpl {
+            sequence: SequenceImpl::named(store_key.clone(), ""),
+            store_key,
+            rows: Rc::new(Rows { marshaller, on_read: RefCell::new(Vec::new()), migrations: RefCell::new(Vec::new()) }),
+            primary_key,
+            indexes: Vec::new(),
+            interceptors: Vec::new(),
+            referenced_by: Vec::new(),
+            changes: None,
+        }
+    }
+
+    /// Adds an interceptor, which runs after the ones added before it. `on_read` also runs for
+    /// reads through this table's indexes.
+    pub fn add_interceptor(&mut self, interceptor: Box<dyn TableInterceptor<K, V>>) {
+        let interceptor: Rc<dyn TableInterceptor<K, V>> = Rc::from(interceptor);
+        let reader = interceptor.clone();
+        self.rows.on_read.borrow_mut().push(Box::new(move |ctx, v| reader.on_read(ctx, v)));
+        self.interceptors.push(interceptor);
+    }
+
+    /// Makes `save` and `delete` emit a `ChangeEvent` for `table` into the `EventManager` of the
+    /// `StoreContext`, once the whole operation including its cascades has succeeded.
+    /// `changed_fields` lists the fields changed by updates.
+    pub fn emit_changes(&mut self, table: &str, changed_fields: Option<ChangedFieldsFn<V>>) {
+        self.changes = Some(ChangeEmitter { table: String::from(table), changed_fields });
+    }
+
+    /// Registers the upgrade of marshalled rows from the current schema version to the next, so
+    /// a table with `n` migrations is at schema version `n`. Rows are tagged with the version
+    /// they were written at, older rows are upgraded when read and written back at the current
+    /// version on their next save. Migrations which change indexed fields need `migrate`.
+    pub fn add_migration(&mut self, migration: Migration) {
+        self.rows.migrations.borrow_mut().push(migration);
+    }
+
+    /// Adds a non-unique secondary index on the key returned by `index_key`, which is kept up
+    /// to date by `save` and `delete`. Indexes must be added in the same order every time the
+    /// table is created, since they are stored by position.
+    pub fn add_index<IK: KeyPart + 'static>(&mut self, name: &str, index_key: Box<dyn Fn(&V) -> IK>) -> SecondaryIndex<IK, V> {
+        let id = self.push_index(name, false, index_key);
+        SecondaryIndex::new(self.store_key.clone(), id, false, self.rows.clone())
+    }
+
+    /// Adds an index like `add_index` which `save` keeps unique, failing with
+    /// `TableError::UniqueConstraint` if another row already has the same index key.
+    pub fn add_unique_index<IK: KeyPart + 'static>(&mut self, name: &str, index_key: Box<dyn Fn(&V) -> IK>) -> UniqueSecondaryIndex<IK, V> {
+        let id = self.push_index(name, true, index_key);
+        UniqueSecondaryIndex::new(SecondaryIndex::new(self.store_key.clone(), id, true, self.rows.clone()))
+    }
+
+    fn push_index<IK: KeyPart + 'static>(&mut self, name: &str, unique: bool, index_key: Box<dyn Fn(&V) -> IK>) -> u8 {
+        if self.indexes.len() > u8::MAX as usize {
+            panic!("too many indexes");
+        }
+        self.indexes.push(IndexDef {
+            name: String::from(name),
+            unique,
+            key: Box::new(move |v| encode_key(&index_key(v))),
+        });
+        (self.indexes.len() - 1) as u8
+    }
+
+    // the index entries of a row with primary key `pk`
+    pub(crate) fn index_entries(&self, pk: &[u8], v: &V) -> Vec<IndexEntry> {
+        self.indexes.iter().enumerate().map(|(id, index)| {
+            let mut key = vec![INDEX_PREFIX, id as u8];
+            key.extend((index.key)(v));
+            if index.unique {
+                IndexEntry { index: id, key, value: Vec::from(pk) }
+            } else {
+                key.extend_from_slice(pk);
+                IndexEntry { index: id, key, value: Vec::new() }
+            }
+        }).collect()
+    }
+
+    /// Returns the row ID of the row with primary key `k`.
+    pub fn row_id(&self, ctx: &dyn StoreContext, k: &K) -> Result<Option<u64>, TableError> {
+        match self.get_raw(ctx, &row_key(k))? {
+            None => Ok(None),
+            Some(bz) => Ok(Some(split_row(&bz)?.0)),
+        }
+    }
+
+    /// Upgrades every row to the current schema version and rebuilds the indexes, returning the
+    /// number of rows upgraded. Like `save`, nothing is written if it fails.
+    pub fn migrate(&self, ctx: &dyn StoreContext) -> Result<u64, TableError> {
+        let mut undo = Undo::default();
+        let res = self.migrate_rows(ctx, &mut undo);
+        undo.finish(ctx, res)
+    }
+
+    fn migrate_rows(&self, ctx: &dyn StoreContext, undo: &mut Undo) -> Result<u64, TableError> {
+        let version = self.rows.schema_version();
+        let mut upgraded = Vec::new();
+        let mut entries = Vec::new();
+        for (key, bz) in read_entries(ctx, &self.store_key, &vec![ROW_PREFIX], Some(&vec![INDEX_PREFIX]), false, usize::MAX)? {
+            let (row_id, value) = self.rows.decode(&bz)?;
+            entries.extend(self.index_entries(&key[1..], &value));
+            if split_row(&bz)?.1 < version {
+                upgraded.push((key, self.rows.encode(row_id, &value)?));
+            }
+        }
+        self.write_indexes(ctx, entries, undo)?;
+        let mut store = ctx.kv_store(&self.store_key)?;
+        for (key, bz) in upgraded.iter() {
+            undo.set(&self.store_key, &mut *store, key, bz)?;
+        }
+        Ok(upgraded.len() as u64)
+    }
+
+    // replaces every index entry with `entries`, checking unique indexes
+    pub(crate) fn write_indexes(&self, ctx: &dyn StoreContext, entries: Vec<IndexEntry>, undo: &mut Undo) -> Result<(), TableError> {
+        let mut index = BTreeMap::new();
+        for entry in entries {
+            if index.insert(entry.key.clone(), entry.value).is_some() {
+                return Err(UniqueConstraint {
+                    index: self.indexes[entry.index].name.clone(),
+                    key: Vec::from(&entry.key[2..]),
+                });
+            }
+        }
+        let old: BTreeMap<_, _> = read_entries(ctx, &self.store_key, &vec![INDEX_PREFIX], None, false, usize::MAX)?.into_iter().collect();
+        let mut store = ctx.kv_store(&self.store_key)?;
+        for key in old.keys().filter(|k| !index.contains_key(*k)) {
+            undo.delete(&self.store_key, &mut *store, key)?;
+        }
+        for (key, value) in index.iter().filter(|(k, v)| old.get(*k) != Some(*v)) {
+            undo.set(&self.store_key, &mut *store, key, value)?;
+        }
+        Ok(())
+    }
+
+    // reads a row without running the on_read hooks
+    pub(crate) fn get_row(&self, ctx: &dyn StoreContext, k: &K) -> Result<Option<V>, TableError> {
+        match self.get_raw(ctx, &row_key(k))? {
+            None => Ok(None),
+            Some(bz) => Ok(Some(self.rows.decode(&bz)?.1)),
+        }
+    }
+
+    pub(crate) fn get_raw(&self, ctx: &dyn StoreContext, key: &Vec<u8>) -> Result<Option<Vec<u8>>, TableError> {
+        let store = ctx.kv_store(&self.store_key)?;
+        store.get(key).map_err(Wrap)
+    }
+
+    fn scan(&self, ctx: &dyn StoreContext, prefix: &[u8], reverse: bool) -> Result<Box<dyn Iterator<K, V>>, TableError> {
+        let start = scan_prefix(prefix);
+        let end = prefix_end(&start);
+        let mut rows = Vec::new();
+        for entry in read_entries(ctx, &self.store_key, &start, end.as_ref(), reverse, usize::MAX)? {
+            if let Some(row) = self.read_entry(ctx, entry)? {
+                rows.push(row);
+            }
+        }
+        Ok(Box::new(RowIterator(rows.into_iter())))
+    }
+
+    fn read_entry(&self, ctx: &dyn StoreContext, (k, v): RawEntry) -> Result<Option<(K, V)>, TableError> {
+        match self.rows.read(ctx, &v)? {
+            None => Ok(None),
+            Some(value) => Ok(Some((decode_key(&k[1..])?, value))),
+        }
+    }
+
+    fn scan_page(&self, ctx: &dyn StoreContext, prefix: &[u8], reverse: bool, page: &PageRequest) -> Result<Page<K, V>, TableError> {
+        scan_page(ctx, &self.store_key, scan_prefix(prefix), reverse, page, |entry| self.read_entry(ctx, entry))
+    }
+}
+
+fn scan_prefix(prefix: &[u8]) -> Vec<u8> {
+    let mut start = vec![ROW_PREFIX];
+    start.extend_from_slice(prefix);
+    start
+}
+
+impl<K: KeyPart + 'static, V: Clone + 'static> TableImpl<K, V> {
+    pub(crate) fn save_row(&self, ctx: &dyn StoreContext, mut row: V, undo: &mut Undo) -> Result<u64, TableError> {
+        let key = row_key(&(self.primary_key)(&row));
+        let (row_id, old) = match self.get_raw(ctx, &key)? {
+            Some(bz) => {
+                let (row_id, old) = self.rows.decode(&bz)?;
+                (row_id, Some(old))
+            }
+            None => {
+                undo.record(&self.store_key, &*ctx.kv_store(&self.store_key)?, &[SEQUENCE_PREFIX])?;
+                (self.sequence.next_val(ctx)?, None)
+            }
+        };
+        let stale = old.as_ref().map_or_else(Vec::new, |old| self.index_entries(&key[1..], old));
+        for interceptor in self.interceptors.iter() {
+            interceptor.before_save(ctx, row_id, &mut row)?;
+        }
+        if row_key(&(self.primary_key)(&row)) != key {
+            return Err(Other(String::from("before_save can't change the primary key")));
+        }
+        let bz = self.rows.encode(row_id, &row)?;
+        let entries = self.index_entries(&key[1..], &row);
+        for entry in entries.iter().filter(|e| self.indexes[e.index].unique && !stale.contains(e)) {
+            if self.get_raw(ctx, &entry.key)?.is_some() {
+                return Err(UniqueConstraint {
+                    index: self.indexes[entry.index].name.clone(),
+                    key: Vec::from(&entry.key[2..]),
+                });
+            }
+        }
+        {
+            let mut store = ctx.kv_store(&self.store_key)?;
+            for entry in stale.iter().filter(|e| !entries.contains(e)) {
+                undo.delete(&self.store_key, &mut *store, &entry.key)?;
+            }
+            for entry in entries.iter().filter(|e| !stale.contains(e)) {
+                undo.set(&self.store_key, &mut *store, &entry.key, &entry.value)?;
+            }
+            undo.set(&self.store_key, &mut *store, &key, &bz)?;
+        }
+        for interceptor in self.interceptors.iter() {
+            interceptor.after_save(ctx, row_id, &row)?;
+        }
+        if let Some(changes) = &self.changes {
+            changes.emit(ctx, undo, &key[1..], old.as_ref(), Some(&row));
+        }
+        Ok(row_id)
+    }
+
+    pub(crate) fn delete_row(&self, ctx: &dyn StoreContext, k: &K, undo: &mut Undo) -> Result<(), TableError> {
+        let key = row_key(k);
+        let (row_id, old) = match self.get_raw(ctx, &key)? {
+            None => return Err(NotFound),
+            Some(bz) => self.rows.decode(&bz)?,
+        };
+        for interceptor in self.interceptors.iter() {
+            interceptor.before_delete(ctx, row_id, k)?;
+        }
+        for references in self.referenced_by.iter() {
+            references.before_delete(ctx, k, undo)?;
+        }
+        {
+            let mut store = ctx.kv_store(&self.store_key)?;
+            for entry in self.index_entries(&key[1..], &old) {
+                undo.delete(&self.store_key, &mut *store, &entry.key)?;
+            }
+            undo.delete(&self.store_key, &mut *store, &key)?;
+        }
+        for interceptor in self.interceptors.iter() {
+            interceptor.after_delete(ctx, row_id, k)?;
+        }
+        if let Some(changes) = &self.changes {
+            changes.emit(ctx, undo, &key[1..], Some(&old), None);
+        }
+        Ok(())
+    }
+}
+
+pub(crate) fn row_key<K: KeyPart>(k: &K) -> Vec<u8> {
+    let mut key = vec![ROW_PREFIX];
+    k.encode_key(&mut key);
+    key
+}
+
+// rows are stored as the 8 byte big-endian row ID, the schema version as an unsigned varint and
+// the marshalled value
+fn split_row(bz: &[u8]) -> Result<(u64, u64, &[u8]), TableError> {
+    if bz.len() < 8 {
+        return Err(UnexpectedState);
+    }
+    match unsigned_varint::decode::u64(&bz[8..]) {
+        Err(_) => Err(UnexpectedState),
+        Ok((version, value)) => Ok((u64::from_be_bytes(bz[..8].try_into().unwrap()), version, value)),
+    }
+}
+
+pub(crate) struct RowIterator<K, V>(pub(crate) std::vec::IntoIter<(K, V)>);
+
+impl<K, V> Iterator<K, V> for RowIterator<K, V> {
+    fn next(&mut self) -> regen_store::Result<Option<(K, V)>> {
+        Ok(self.0.next())
+    }
+}
+
+impl<K: KeyPart + 'static, V: 'static> Index<K, V> for TableImpl<K, V> {
+    fn has(&self, ctx: &dyn StoreContext, key: &K) -> Result<bool, TableError> {
+        let store = ctx.kv_store(&self.store_key)?;
+        store.has(&row_key(key)).map_err(Wrap)
+    }
+
+    fn get(&self, ctx: &dyn StoreContext, key: &K) -> Result<Box<dyn Iterator<K, V>>, TableError> {
+        self.scan(ctx, &encode_key(key), false)
+    }
+
+    fn prefix_scan(&self, ctx: &dyn StoreContext, prefix: &[u8]) -> Result<Box<dyn Iterator<K, V>>, TableError> {
+        self.scan(ctx, prefix, false)
+    }
+
+    fn reverse_prefix_scan(&self, ctx: &dyn StoreContext, prefix: &[u8]) -> Result<Box<dyn Iterator<K, V>>, TableError> {
+        self.scan(ctx, prefix, true)
+    }
+
+    fn prefix_scan_page(&self, ctx: &dyn StoreContext, prefix: &[u8], page: &PageRequest) -> Result<Page<K, V>, TableError> {
+        self.scan_page(ctx, prefix, false, page)
+    }
+
+    fn reverse_prefix_scan_page(&self, ctx: &dyn StoreContext, prefix: &[u8], page: &PageRequest) -> Result<Page<K, V>, TableError> {
+        self.scan_page(ctx, prefix, true, page)
+    }
+}
+
+impl<K: KeyPart + 'static, V: 'static> UniqueIndex<K, V> for TableImpl<K, V> {
+    fn get_one(&self, ctx: &dyn StoreContext, key: &K) -> Result<(K, V), TableError> {
+        match self.get_raw(ctx, &row_key(key))? {
+            None => Err(NotFound),
+            Some(bz) => match self.rows.read(ctx, &bz)? {
+                None => Err(NotFound),
+                Some(value) => Ok(((self.primary_key)(&value), value)),
+            }
+        }
+    }
+}
+
+/// Interceptors run around every `save` and `delete`. If any of them fails, the writes made so
+/// far are rolled back and the error is returned.
+impl<K: KeyPart + 'static, V: Clone + 'static> Table<K, V> for TableImpl<K, V> {
+    fn delete(&self, ctx: &dyn StoreContext, k: &K) -> Result<(), TableError> {
+        let mut undo = Undo::default();
+        let res = self.delete_row(ctx, k, &mut undo);
+        undo.finish(ctx, res)
+    }
+
+    fn save(&self, ctx: &dyn StoreContext, v: &V) -> Result<u64, TableError> {
+        let mut undo = Undo::default();
+        let res = self.save_row(ctx, v.clone(), &mut undo);
+        undo.finish(ctx, res)
+    }
+}
@@ -0,0 +1,127 @@
+use std::rc::Rc;
+use regen_store::mem::MemStore;
+use regen_context::event::EventManager;
+use regen_table::{Index, UniqueIndex, Table, TableImpl, Marshaller, SimpleStoreContext, StoreContext, StoreKey, TableError, OnDelete};
+use regen_table::key::{encode_key, decode_key};
+
+#[derive(Debug, Clone, PartialEq)]
+struct Site(String);
+
+#[derive(Debug, Clone, PartialEq)]
+struct Observation {
+    id: u64,
+    site: Option<String>,
+}
+
+struct SiteMarshaller;
+
+impl Marshaller<Site> for SiteMarshaller {
+    fn marshal(&self, value: &Site) -> Result<Vec<u8>, TableError> {
+        Ok(encode_key(&value.0))
+    }
+
+    fn unmarshal(&self, bz: &[u8]) -> Result<Site, TableError> {
+        Ok(Site(decode_key(bz)?))
+    }
+}
+
+struct ObservationMarshaller;
+
+impl Marshaller<Observation> for ObservationMarshaller {
+    fn marshal(&self, value: &Observation) -> Result<Vec<u8>, TableError> {
+        Ok(encode_key(&(value.id, value.site.clone())))
+    }
+
+    fn unmarshal(&self, bz: &[u8]) -> Result<Observation, TableError> {
+        let (id, site) = decode_key(bz)?;
+        Ok(Observation { id, site })
+    }
+}
+
+fn observation(id: u64, site: &str) -> Observation {
+    Observation { id, site: Some(String::from(site)) }
+}
+
+fn tables(on_delete: OnDelete<Observation>) -> (TableImpl<String, Site>, Rc<TableImpl<u64, Observation>>) {
+    let mut sites = TableImpl::new(StoreKey::new("sites"), Box::new(SiteMarshaller), Box::new(|s: &Site| s.0.clone()));
+    let mut observations = TableImpl::new(StoreKey::new("observations"), Box::new(ObservationMarshaller), Box::new(|o: &Observation| o.id));
+    let site = observations.add_foreign_key("site", &sites, Box::new(|o: &Observation| o.site.clone()));
+    sites.emit_changes("sites", None);
+    observations.emit_changes("observations", None);
+    let observations = Rc::new(observations);
+    sites.add_referencing_table(site, observations.clone(), on_delete);
+    (sites, observations)
+}
+
+fn with_stores(on_delete: OnDelete<Observation>, test: impl Fn(&SimpleStoreContext, &TableImpl<String, Site>, &TableImpl<u64, Observation>)) {
+    let mut sites_store = MemStore::new();
+    let mut observations_store = MemStore::new();
+    let events = EventManager::new();
+    let mut ctx = SimpleStoreContext::new();
+    ctx.set_events(&events);
+    ctx.mount(StoreKey::new("sites"), &mut sites_store).unwrap();
+    ctx.mount(StoreKey::new("observations"), &mut observations_store).unwrap();
+    let (sites, observations) = tables(on_delete);
+    sites.save(&ctx, &Site(String::from("forest"))).unwrap();
+    sites.save(&ctx, &Site(String::from("meadow"))).unwrap();
+    observations.save(&ctx, &observation(1, "forest")).unwrap();
+    observations.save(&ctx, &observation(2, "forest")).unwrap();
+    observations.save(&ctx, &observation(3, "meadow")).unwrap();
+    events.take();
+    test(&ctx, &sites, &observations);
+}
+
+#[test]
+fn test_missing_reference() {
+    with_stores(OnDelete::Restrict, |ctx, _, observations| {
+        assert!(matches!(observations.save(ctx, &observation(4, "desert")), Err(TableError::ForeignKeyConstraint { .. })));
+        assert!(!observations.has(ctx, &4).unwrap());
+        observations.save(ctx, &Observation { id: 4, site: None }).unwrap();
+    });
+}
+
+#[test]
+fn test_restrict() {
+    with_stores(OnDelete::Restrict, |ctx, sites, observations| {
+        let forest = String::from("forest");
+        assert!(matches!(sites.delete(ctx, &forest), Err(TableError::ForeignKeyConstraint { .. })));
+        assert!(sites.has(ctx, &forest).unwrap());
+        observations.delete(ctx, &1).unwrap();
+        observations.delete(ctx, &2).unwrap();
+        sites.delete(ctx, &forest).unwrap();
+    });
+}
+
+#[test]
+fn test_cascade() {
+    with_stores(OnDelete::Cascade, |ctx, sites, observations| {
+        sites.delete(ctx, &String::from("forest")).unwrap();
+        assert!(!observations.has(ctx, &1).unwrap());
+        assert!(!observations.has(ctx, &2).unwrap());
+        assert!(observations.has(ctx, &3).unwrap());
+        let tables: Vec<String> = ctx.events().unwrap().take().iter().map(|e| e.attributes[0].value.clone()).collect();
+        assert_eq!(tables, vec!["observations", "observations", "sites"]);
+    });
+}
+
+#[test]
+fn test_set_null() {
+    with_stores(OnDelete::SetNull(Box::new(|o| o.site = None)), |ctx, sites, observations| {
+        sites.delete(ctx, &String::from("forest")).unwrap();
+        assert_eq!(observations.get_one(ctx, &1).unwrap().1.site, None);
+        assert_eq!(observations.get_one(ctx, &3).unwrap().1.site, Some(String::from("meadow")));
+    });
+}
+
+#[test]
+fn test_failed_delete_rolls_back_children() {
+    // clearing the second observation fails after the first one was saved
+    with_stores(OnDelete::SetNull(Box::new(|o| if o.id == 1 { o.site = None })), |ctx, sites, observations| {
+        assert!(sites.delete(ctx, &String::from("forest")).is_err());
+        assert!(sites.has(ctx, &String::from("forest")).unwrap());
+        assert_eq!(observations.get_one(ctx, &1).unwrap().1.site, Some(String::from("forest")));
+        assert_eq!(observations.get_one(ctx, &2).unwrap().1.site, Some(String::from("forest")));
+        // nor are the changes of the first observation emitted
+        assert!(ctx.events().unwrap().take().is_empty());
+    });
+}
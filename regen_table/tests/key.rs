@@ -0,0 +1,61 @@
+use proptest::prelude::*;
+use regen_table::key::{encode_key, decode_key, prefix_end, KeyBuilder};
+
+proptest! {
+    #[test]
+    fn test_u64_order(a in any::<u64>(), b in any::<u64>()) {
+        prop_assert_eq!(a.cmp(&b), encode_key(&a).cmp(&encode_key(&b)));
+        prop_assert_eq!(decode_key::<u64>(&encode_key(&a)).unwrap(), a);
+    }
+
+    #[test]
+    fn test_i64_order(a in any::<i64>(), b in any::<i64>()) {
+        prop_assert_eq!(a.cmp(&b), encode_key(&a).cmp(&encode_key(&b)));
+        prop_assert_eq!(decode_key::<i64>(&encode_key(&a)).unwrap(), a);
+    }
+
+    #[test]
+    fn test_tuple_order(a in any::<(Vec<u8>, i32, bool)>(), b in any::<(Vec<u8>, i32, bool)>()) {
+        prop_assert_eq!(a.cmp(&b), encode_key(&a).cmp(&encode_key(&b)));
+        prop_assert_eq!(decode_key::<(Vec<u8>, i32, bool)>(&encode_key(&a)).unwrap(), a);
+    }
+
+    #[test]
+    fn test_string_tuple_order(a in any::<(String, u64)>(), b in any::<(String, u64)>()) {
+        prop_assert_eq!(a.cmp(&b), encode_key(&a).cmp(&encode_key(&b)));
+        prop_assert_eq!(decode_key::<(String, u64)>(&encode_key(&a)).unwrap(), a);
+    }
+
+    #[test]
+    fn test_option_order(a in any::<Option<(String, u32)>>(), b in any::<Option<(String, u32)>>()) {
+        prop_assert_eq!(a.cmp(&b), encode_key(&a).cmp(&encode_key(&b)));
+        prop_assert_eq!(decode_key::<Option<(String, u32)>>(&encode_key(&a)).unwrap(), a);
+    }
+
+    #[test]
+    fn test_prefix(a in any::<(String, u16)>()) {
+        let prefix = KeyBuilder::new().push(&a.0).build();
+        let key = encode_key(&a);
+        prop_assert!(key.starts_with(&prefix));
+        match prefix_end(&prefix) {
+            None => {},
+            Some(end) => prop_assert!(key < end),
+        }
+    }
+}
+
+#[test]
+fn test_decode_errors() {
+    assert!(decode_key::<u32>(&[0, 1]).is_err());
+    assert!(decode_key::<bool>(&[2]).is_err());
+    assert!(decode_key::<Option<u8>>(&[2, 0]).is_err());
+    assert!(decode_key::<Vec<u8>>(&[1, 2]).is_err());
+    assert!(decode_key::<u8>(&[1, 2]).is_err());
+}
+
+#[test]
+fn test_prefix_end() {
+    assert_eq!(prefix_end(&[1, 2]), Some(vec![1, 3]));
+    assert_eq!(prefix_end(&[1, 0xFF]), Some(vec![2]));
+    assert_eq!(prefix_end(&[0xFF, 0xFF]), None);
+}
@@ -0,0 +1,216 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+use regen_store::{Map, OrderedMap, MutableMap, MutableOrderedMap, Iterator};
+use regen_store::mem::MemStore;
+use regen_table::{Table, TableRow, TableInterceptor, Marshaller, SimpleStoreContext, StoreContext, StoreKey, TableError, PageRequest};
+use regen_table::key::{encode_key, decode_key};
+
+#[derive(Debug, Clone, PartialEq, TableRow)]
+struct CreditBatch {
+    #[primary_key]
+    denom: String,
+    #[index]
+    class: String,
+    #[index]
+    vintage: u32,
+    #[unique]
+    number: u64,
+}
+
+struct CreditBatchMarshaller;
+
+impl Marshaller<CreditBatch> for CreditBatchMarshaller {
+    fn marshal(&self, value: &CreditBatch) -> Result<Vec<u8>, TableError> {
+        Ok(encode_key(&(value.denom.clone(), value.class.clone(), value.vintage, value.number)))
+    }
+
+    fn unmarshal(&self, bz: &[u8]) -> Result<CreditBatch, TableError> {
+        let (denom, class, vintage, number) = decode_key(bz)?;
+        Ok(CreditBatch { denom, class, vintage, number })
+    }
+}
+
+fn batch(denom: &str, class: &str, vintage: u32, number: u64) -> CreditBatch {
+    CreditBatch { denom: String::from(denom), class: String::from(class), vintage, number }
+}
+
+fn denoms(items: &[(String, CreditBatch)]) -> Vec<&str> {
+    items.iter().map(|(k, _)| k.as_str()).collect()
+}
+
+#[test]
+fn test_query() {
+    let mut store = MemStore::new();
+    let mut ctx = SimpleStoreContext::new();
+    ctx.mount(StoreKey::new("batches"), &mut store).unwrap();
+    let (table, indexes) = CreditBatch::table(StoreKey::new("batches"), Box::new(CreditBatchMarshaller));
+    table.save(&ctx, &batch("C01-001", "C01", 2022, 1)).unwrap();
+    table.save(&ctx, &batch("C01-002", "C01", 2023, 2)).unwrap();
+    table.save(&ctx, &batch("C02-001", "C02", 2024, 3)).unwrap();
+    table.save(&ctx, &batch("C01-004", "C01", 2025, 4)).unwrap();
+    table.save(&ctx, &batch("C01-003", "C01", 2024, 5)).unwrap();
+    table.save(&ctx, &batch("C01-005", "C01", 2026, 6)).unwrap();
+    let all = PageRequest { limit: 10, ..Default::default() };
+
+    let query = table.query().eq(&indexes.class, &String::from("C01")).range(&indexes.vintage, 2023..=2025);
+    let page = query.page(&ctx, &all).unwrap();
+    assert_eq!(denoms(&page.items), vec!["C01-002", "C01-003", "C01-004"]);
+    assert_eq!(page.next_cursor, None);
+
+    let page = table.query().range(&indexes.vintage, 2023..2026).range(&indexes.number, ..5).page(&ctx, &all).unwrap();
+    assert_eq!(denoms(&page.items), vec!["C01-002", "C01-004", "C02-001"]);
+    let page = table.query().range(&indexes.vintage, (std::ops::Bound::Excluded(2025), std::ops::Bound::Unbounded)).page(&ctx, &all).unwrap();
+    assert_eq!(denoms(&page.items), vec!["C01-005"]);
+    let page = table.query().eq(&indexes.class, &String::from("C02")).eq(&indexes.number, &4).page(&ctx, &all).unwrap();
+    assert!(page.items.is_empty());
+
+    // pagination
+    let mut req = PageRequest { limit: 2, count_total: true, ..Default::default() };
+    let page = query.page(&ctx, &req).unwrap();
+    assert_eq!(denoms(&page.items), vec!["C01-002", "C01-003"]);
+    assert_eq!(page.total, Some(3));
+    req.cursor = page.next_cursor;
+    let page = query.page(&ctx, &req).unwrap();
+    assert_eq!(denoms(&page.items), vec!["C01-004"]);
+    assert_eq!(page.next_cursor, None);
+
+    assert!(table.query().page(&ctx, &all).is_err());
+}
+
+struct HideDenom(&'static str);
+
+impl TableInterceptor<String, CreditBatch> for HideDenom {
+    fn on_read(&self, _ctx: &dyn StoreContext, value: CreditBatch) -> Result<Option<CreditBatch>, TableError> {
+        Ok(if value.denom == self.0 { None } else { Some(value) })
+    }
+}
+
+#[test]
+fn test_query_changed_and_hidden_rows() {
+    let mut store = MemStore::new();
+    let mut ctx = SimpleStoreContext::new();
+    ctx.mount(StoreKey::new("batches"), &mut store).unwrap();
+    let (mut table, indexes) = CreditBatch::table(StoreKey::new("batches"), Box::new(CreditBatchMarshaller));
+    table.add_interceptor(Box::new(HideDenom("C01-003")));
+    for (i, denom) in ["C01-001", "C01-002", "C01-003", "C01-004", "C01-005"].iter().enumerate() {
+        table.save(&ctx, &batch(denom, "C01", 2020 + i as u32, i as u64)).unwrap();
+    }
+    // rows moved out of the class or deleted aren't found through their old index entries
+    table.save(&ctx, &batch("C01-002", "C02", 2021, 1)).unwrap();
+    table.delete(&ctx, &String::from("C01-005")).unwrap();
+    let query = table.query().eq(&indexes.class, &String::from("C01"));
+    let all = PageRequest { limit: 10, count_total: true, ..Default::default() };
+    let page = query.page(&ctx, &all).unwrap();
+    assert_eq!(denoms(&page.items), vec!["C01-001", "C01-004"]);
+    assert_eq!(page.total, Some(3));
+
+    // pages skip the hidden row
+    let mut req = PageRequest { limit: 1, ..Default::default() };
+    let mut pages = Vec::new();
+    loop {
+        let page = query.page(&ctx, &req).unwrap();
+        pages.push(denoms(&page.items).iter().map(|d| String::from(*d)).collect::<Vec<_>>());
+        match page.next_cursor {
+            None => break,
+            cursor => req.cursor = cursor,
+        }
+    }
+    assert_eq!(pages.concat(), vec!["C01-001", "C01-004"]);
+}
+
+#[test]
+fn test_query_unique_and_range() {
+    let mut store = MemStore::new();
+    let mut ctx = SimpleStoreContext::new();
+    ctx.mount(StoreKey::new("batches"), &mut store).unwrap();
+    let (table, indexes) = CreditBatch::table(StoreKey::new("batches"), Box::new(CreditBatchMarshaller));
+    table.save(&ctx, &batch("C01-001", "C01", 2022, 1)).unwrap();
+    table.save(&ctx, &batch("C01-002", "C01", 2023, 2)).unwrap();
+    table.save(&ctx, &batch("C02-001", "C02", 2024, 3)).unwrap();
+    table.save(&ctx, &batch("C01-004", "C01", 2025, 4)).unwrap();
+    table.save(&ctx, &batch("C01-003", "C01", 2024, 5)).unwrap();
+    let all = PageRequest { limit: 10, ..Default::default() };
+
+    let page = table.query().eq(&indexes.number, &5).range(&indexes.vintage, 2024..).page(&ctx, &all).unwrap();
+    assert_eq!(denoms(&page.items), vec!["C01-003"]);
+    let page = table.query().eq(&indexes.number, &5).range(&indexes.vintage, ..2024).page(&ctx, &all).unwrap();
+    assert!(page.items.is_empty());
+    let page = table.query().range(&indexes.number, 2..=5).eq(&indexes.class, &String::from("C01")).page(&ctx, &all).unwrap();
+    assert_eq!(denoms(&page.items), vec!["C01-002", "C01-003", "C01-004"]);
+    let page = table.query().range(&indexes.number, 3..).page(&ctx, &PageRequest { limit: 2, count_total: true, ..Default::default() }).unwrap();
+    assert_eq!(denoms(&page.items), vec!["C01-003", "C01-004"]);
+    assert_eq!(page.total, Some(3));
+    assert_eq!(page.next_cursor, Some(encode_key(&String::from("C01-004"))));
+}
+
+// a store which records the keys of the entries read from it
+struct RecordingStore {
+    store: MemStore,
+    read: Rc<RefCell<Vec<Vec<u8>>>>,
+}
+
+struct RecordingIterator<'a> {
+    it: Box<dyn Iterator<Vec<u8>, Vec<u8>> + 'a>,
+    read: Rc<RefCell<Vec<Vec<u8>>>>,
+}
+
+impl<'a> Iterator<Vec<u8>, Vec<u8>> for RecordingIterator<'a> {
+    fn next(&mut self) -> regen_store::Result<Option<(Vec<u8>, Vec<u8>)>> {
+        let entry = self.it.next()?;
+        if let Some((k, _)) = &entry {
+            self.read.borrow_mut().push(k.clone());
+        }
+        Ok(entry)
+    }
+}
+
+impl Map<Vec<u8>, Vec<u8>> for RecordingStore {
+    fn get(&self, key: &Vec<u8>) -> regen_store::Result<Option<Vec<u8>>> {
+        self.read.borrow_mut().push(key.clone());
+        self.store.get(key)
+    }
+
+    fn has(&self, key: &Vec<u8>) -> regen_store::Result<bool> {
+        self.read.borrow_mut().push(key.clone());
+        self.store.has(key)
+    }
+}
+
+impl OrderedMap<Vec<u8>, Vec<u8>> for RecordingStore {
+    fn iterator(&self, start: Option<&Vec<u8>>, end: Option<&Vec<u8>>) -> regen_store::Result<Box<dyn Iterator<Vec<u8>, Vec<u8>> + '_>> {
+        Ok(Box::new(RecordingIterator { it: self.store.iterator(start, end)?, read: self.read.clone() }))
+    }
+
+    fn reverse_iterator(&self, start: Option<&Vec<u8>>, end: Option<&Vec<u8>>) -> regen_store::Result<Box<dyn Iterator<Vec<u8>, Vec<u8>> + '_>> {
+        Ok(Box::new(RecordingIterator { it: self.store.reverse_iterator(start, end)?, read: self.read.clone() }))
+    }
+}
+
+impl MutableMap<Vec<u8>, Vec<u8>> for RecordingStore {
+    fn set(&mut self, key: &Vec<u8>, value: &Vec<u8>) -> regen_store::Result<()> {
+        self.store.set(key, value)
+    }
+
+    fn delete(&mut self, key: &Vec<u8>) -> regen_store::Result<()> {
+        self.store.delete(key)
+    }
+}
+
+impl MutableOrderedMap<Vec<u8>, Vec<u8>> for RecordingStore {}
+
+#[test]
+fn test_range_query_reads_index_range() {
+    let read = Rc::new(RefCell::new(Vec::new()));
+    let mut store = RecordingStore { store: MemStore::new(), read: read.clone() };
+    let mut ctx = SimpleStoreContext::new();
+    ctx.mount(StoreKey::new("batches"), &mut store).unwrap();
+    let (table, indexes) = CreditBatch::table(StoreKey::new("batches"), Box::new(CreditBatchMarshaller));
+    for i in 0..20 {
+        table.save(&ctx, &batch(&format!("C01-{:03}", i), "C01", 2000 + i, i as u64)).unwrap();
+    }
+    read.borrow_mut().clear();
+    let page = table.query().range(&indexes.vintage, 2005..2008).page(&ctx, &PageRequest { limit: 10, ..Default::default() }).unwrap();
+    assert_eq!(denoms(&page.items), vec!["C01-005", "C01-006", "C01-007"]);
+    // the three index entries in the range and the three rows
+    assert_eq!(read.borrow().len(), 6);
+}
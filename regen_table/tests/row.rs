@@ -0,0 +1,72 @@
+use regen_store::Iterator;
+use regen_store::mem::MemStore;
+use regen_table::{Index, UniqueIndex, Table, TableRow, Marshaller, SimpleStoreContext, StoreKey, TableError};
+use regen_table::key::{KeyBuilder, encode_key, decode_key};
+
+#[derive(Debug, Clone, PartialEq, TableRow)]
+struct Balance {
+    #[primary_key]
+    owner: String,
+    #[primary_key]
+    denom: String,
+    #[index]
+    amount: u64,
+    #[unique]
+    number: u32,
+    memo: String,
+}
+
+struct BalanceMarshaller;
+
+impl Marshaller<Balance> for BalanceMarshaller {
+    fn marshal(&self, value: &Balance) -> Result<Vec<u8>, TableError> {
+        Ok(encode_key(&(value.owner.clone(), value.denom.clone(), value.amount, value.number, value.memo.clone())))
+    }
+
+    fn unmarshal(&self, bz: &[u8]) -> Result<Balance, TableError> {
+        let (owner, denom, amount, number, memo) = decode_key(bz)?;
+        Ok(Balance { owner, denom, amount, number, memo })
+    }
+}
+
+fn balance(owner: &str, denom: &str, amount: u64, number: u32) -> Balance {
+    Balance { owner: String::from(owner), denom: String::from(denom), amount, number, memo: String::new() }
+}
+
+fn collect<K, V>(mut it: Box<dyn Iterator<K, V>>) -> Vec<(K, V)> {
+    let mut res = Vec::new();
+    while let Some(kv) = it.next().unwrap() {
+        res.push(kv);
+    }
+    res
+}
+
+#[test]
+fn test_derive_table_row() {
+    let mut store = MemStore::new();
+    let mut ctx = SimpleStoreContext::new();
+    ctx.mount(StoreKey::new("balances"), &mut store).unwrap();
+    let (table, indexes) = Balance::table(StoreKey::new("balances"), Box::new(BalanceMarshaller));
+    table.save(&ctx, &balance("alice", "regen", 10, 1)).unwrap();
+    table.save(&ctx, &balance("alice", "atom", 20, 2)).unwrap();
+    table.save(&ctx, &balance("bob", "regen", 10, 3)).unwrap();
+
+    let key = (String::from("alice"), String::from("atom"));
+    assert_eq!(balance("alice", "atom", 20, 2).primary_key(), key);
+    assert_eq!(table.get_one(&ctx, &key).unwrap().1.amount, 20);
+    assert_eq!(Balance::encode_primary_key(&key), encode_key(&key));
+
+    // partial primary key scans
+    let prefix = KeyBuilder::new().push(&String::from("alice")).build();
+    let denoms: Vec<String> = collect(table.prefix_scan(&ctx, &prefix).unwrap()).into_iter().map(|(k, _)| k.1).collect();
+    assert_eq!(denoms, vec![String::from("atom"), String::from("regen")]);
+
+    let tens: Vec<String> = collect(indexes.amount.get(&ctx, &10).unwrap()).into_iter().map(|(_, v)| v.owner).collect();
+    assert_eq!(tens, vec![String::from("alice"), String::from("bob")]);
+    assert_eq!(indexes.number.get_one(&ctx, &3).unwrap().1.owner, "bob");
+    assert!(matches!(table.save(&ctx, &balance("carol", "regen", 5, 3)), Err(TableError::UniqueConstraint { .. })));
+
+    // keys encode in order
+    assert!(Balance::encode_amount_key(&9) < Balance::encode_amount_key(&10));
+    assert!(Balance::encode_number_key(&255) < Balance::encode_number_key(&256));
+}
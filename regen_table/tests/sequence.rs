@@ -0,0 +1,29 @@
+use regen_store::mem::MemStore;
+use regen_table::{Sequence, SequenceImpl, SimpleStoreContext, StoreKey};
+
+#[test]
+fn test_named_sequences() {
+    let mut store = MemStore::new();
+    let mut ctx = SimpleStoreContext::new();
+    ctx.mount(StoreKey::new("ids"), &mut store).unwrap();
+    let batches = SequenceImpl::named(StoreKey::new("ids"), "batches");
+    let classes = SequenceImpl::named(StoreKey::new("ids"), "classes");
+
+    assert_eq!(batches.next_val(&ctx).unwrap(), 1);
+    assert_eq!(batches.next_val(&ctx).unwrap(), 2);
+    assert_eq!(classes.cur_val(&ctx).unwrap(), 0);
+    assert_eq!(classes.next_val(&ctx).unwrap(), 1);
+
+    assert_eq!(batches.reserve(&ctx, 10).unwrap(), 3..13);
+    assert_eq!(batches.next_val(&ctx).unwrap(), 13);
+    assert_eq!(batches.reserve(&ctx, 0).unwrap(), 14..14);
+
+    classes.set_val(&ctx, 100).unwrap();
+    assert_eq!(classes.next_val(&ctx).unwrap(), 101);
+    assert!(classes.set_val(&ctx, 50).is_err());
+
+    classes.set_val(&ctx, u64::MAX - 2).unwrap();
+    assert!(classes.reserve(&ctx, 2).is_err());
+    assert_eq!(classes.cur_val(&ctx).unwrap(), u64::MAX - 2);
+    assert_eq!(classes.reserve(&ctx, 1).unwrap(), u64::MAX - 1..u64::MAX);
+}
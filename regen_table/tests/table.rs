@@ -0,0 +1,508 @@
+use std::cell::{RefCell, RefMut};
+use std::rc::Rc;
+use regen_store::{Iterator, Batch, OrderedMap};
+use regen_store::mem::MemStore;
+use regen_table::{Index, UniqueIndex, Table, TableImpl, TableInterceptor, SecondaryIndex, StoreContext, Marshaller, SimpleStoreContext, StoreKey, TableError, PageRequest, JsonMarshaller, ChangeOp, KVStore, KVStoreRef, STORE_CONTEXT, store_context};
+use regen_context::SimpleContext;
+use regen_context::event::{Event, EventManager, EVENTS, collect_events, with_events};
+use serde_json::{json, Value};
+use regen_table::key::{KeyBuilder, encode_key, decode_key};
+
+#[derive(Debug, Clone, PartialEq)]
+struct Account {
+    owner: String,
+    number: u32,
+    balance: u64,
+}
+
+struct AccountMarshaller;
+
+impl Marshaller<Account> for AccountMarshaller {
+    fn marshal(&self, value: &Account) -> Result<Vec<u8>, TableError> {
+        let mut bz = value.number.to_be_bytes().to_vec();
+        bz.extend_from_slice(&value.balance.to_be_bytes());
+        bz.extend_from_slice(value.owner.as_bytes());
+        Ok(bz)
+    }
+
+    fn unmarshal(&self, bz: &[u8]) -> Result<Account, TableError> {
+        let mut number = [0; 4];
+        let mut balance = [0; 8];
+        number.copy_from_slice(&bz[..4]);
+        balance.copy_from_slice(&bz[4..12]);
+        Ok(Account {
+            owner: String::from_utf8(bz[12..].to_vec()).unwrap(),
+            number: u32::from_be_bytes(number),
+            balance: u64::from_be_bytes(balance),
+        })
+    }
+}
+
+struct AccountJson;
+
+impl JsonMarshaller<Account> for AccountJson {
+    fn marshal_json(&self, value: &Account) -> Result<Value, TableError> {
+        Ok(json!({"owner": value.owner, "number": value.number, "balance": value.balance}))
+    }
+
+    fn unmarshal_json(&self, json: &Value) -> Result<Account, TableError> {
+        match (json["owner"].as_str(), json["number"].as_u64(), json["balance"].as_u64()) {
+            (Some(owner), Some(number), Some(balance)) if number <= u64::from(u32::MAX) => Ok(account(owner, number as u32, balance)),
+            _ => Err(TableError::Other(format!("invalid account {}", json))),
+        }
+    }
+}
+
+fn account(owner: &str, number: u32, balance: u64) -> Account {
+    Account { owner: String::from(owner), number, balance }
+}
+
+fn accounts_table() -> TableImpl<(String, u32), Account> {
+    TableImpl::new(
+        StoreKey::new("accounts"),
+        Box::new(AccountMarshaller),
+        Box::new(|a: &Account| (a.owner.clone(), a.number)),
+    )
+}
+
+fn collect<K, V>(mut it: Box<dyn Iterator<K, V>>) -> Vec<(K, V)> {
+    let mut res = Vec::new();
+    while let Some(kv) = it.next().unwrap() {
+        res.push(kv);
+    }
+    res
+}
+
+#[test]
+fn test_table() {
+    let mut store = MemStore::new();
+    let mut ctx = SimpleStoreContext::new();
+    ctx.mount(StoreKey::new("accounts"), &mut store).unwrap();
+    let table = accounts_table();
+
+    assert_eq!(table.save(&ctx, &account("bob", 1, 10)).unwrap(), 1);
+    assert_eq!(table.save(&ctx, &account("alice", 2, 20)).unwrap(), 2);
+    assert_eq!(table.save(&ctx, &account("alice", 1, 30)).unwrap(), 3);
+    // updates keep the row ID
+    assert_eq!(table.save(&ctx, &account("bob", 1, 15)).unwrap(), 1);
+
+    let key = (String::from("bob"), 1);
+    assert!(table.has(&ctx, &key).unwrap());
+    assert_eq!(table.get_one(&ctx, &key).unwrap(), (key.clone(), account("bob", 1, 15)));
+    assert_eq!(table.row_id(&ctx, &key).unwrap(), Some(1));
+    assert_eq!(collect(table.get(&ctx, &key).unwrap()).len(), 1);
+
+    let alice = KeyBuilder::new().push(&String::from("alice")).build();
+    let rows: Vec<_> = collect(table.prefix_scan(&ctx, &alice).unwrap()).into_iter().map(|(_, v)| v).collect();
+    assert_eq!(rows, vec![account("alice", 1, 30), account("alice", 2, 20)]);
+    let rows: Vec<_> = collect(table.reverse_prefix_scan(&ctx, &[]).unwrap()).into_iter().map(|(k, _)| k).collect();
+    assert_eq!(rows, vec![key.clone(), (String::from("alice"), 2), (String::from("alice"), 1)]);
+
+    table.delete(&ctx, &key).unwrap();
+    assert!(!table.has(&ctx, &key).unwrap());
+    assert!(matches!(table.get_one(&ctx, &key), Err(TableError::NotFound)));
+    assert!(matches!(table.delete(&ctx, &key), Err(TableError::NotFound)));
+    // row IDs aren't reused
+    assert_eq!(table.save(&ctx, &account("bob", 1, 0)).unwrap(), 4);
+}
+
+#[test]
+fn test_secondary_index() {
+    let mut store = MemStore::new();
+    let mut ctx = SimpleStoreContext::new();
+    ctx.mount(StoreKey::new("accounts"), &mut store).unwrap();
+    let mut table = accounts_table();
+    let by_balance: SecondaryIndex<u64, Account> = table.add_index("balance", Box::new(|a: &Account| a.balance));
+    let by_number = table.add_index("number", Box::new(|a: &Account| (a.number, a.owner.clone())));
+
+    table.save(&ctx, &account("bob", 1, 10)).unwrap();
+    table.save(&ctx, &account("alice", 2, 20)).unwrap();
+    table.save(&ctx, &account("alice", 1, 10)).unwrap();
+
+    assert!(by_balance.has(&ctx, &10).unwrap());
+    assert!(!by_balance.has(&ctx, &15).unwrap());
+    // equal index keys are ordered by primary key
+    assert_eq!(collect(by_balance.get(&ctx, &10).unwrap()), vec![(10, account("alice", 1, 10)), (10, account("bob", 1, 10))]);
+    let ones = KeyBuilder::new().push(&1u32).build();
+    let owners: Vec<_> = collect(by_number.reverse_prefix_scan(&ctx, &ones).unwrap()).into_iter().map(|((_, o), _)| o).collect();
+    assert_eq!(owners, vec![String::from("bob"), String::from("alice")]);
+
+    // updating the indexed field moves the entry
+    table.save(&ctx, &account("bob", 1, 20)).unwrap();
+    assert_eq!(collect(by_balance.get(&ctx, &10).unwrap()), vec![(10, account("alice", 1, 10))]);
+    let twenties: Vec<_> = collect(by_balance.get(&ctx, &20).unwrap()).into_iter().map(|(_, a)| a.owner).collect();
+    assert_eq!(twenties, vec![String::from("alice"), String::from("bob")]);
+
+    table.delete(&ctx, &(String::from("alice"), 1)).unwrap();
+    assert!(!by_balance.has(&ctx, &10).unwrap());
+    assert_eq!(collect(by_number.prefix_scan(&ctx, &ones).unwrap()).len(), 1);
+    assert_eq!(collect(by_balance.prefix_scan(&ctx, &[]).unwrap()).len(), 2);
+}
+
+#[test]
+fn test_unique_index() {
+    let mut store = MemStore::new();
+    let mut ctx = SimpleStoreContext::new();
+    ctx.mount(StoreKey::new("accounts"), &mut store).unwrap();
+    let mut table = accounts_table();
+    let by_balance = table.add_unique_index("balance", Box::new(|a: &Account| a.balance));
+
+    table.save(&ctx, &account("bob", 1, 10)).unwrap();
+    table.save(&ctx, &account("alice", 1, 20)).unwrap();
+    // re-saving the same row is fine
+    table.save(&ctx, &account("bob", 1, 10)).unwrap();
+    assert_eq!(by_balance.get_one(&ctx, &10).unwrap(), (10, account("bob", 1, 10)));
+    assert!(matches!(by_balance.get_one(&ctx, &30), Err(TableError::NotFound)));
+
+    match table.save(&ctx, &account("carol", 1, 10)) {
+        Err(TableError::UniqueConstraint { index, key }) => {
+            assert_eq!(index, "balance");
+            assert_eq!(decode_key::<u64>(&key).unwrap(), 10);
+        }
+        res => panic!("expected a unique constraint error, got {:?}", res),
+    }
+    // nothing was written
+    assert!(!table.has(&ctx, &(String::from("carol"), 1)).unwrap());
+    assert!(table.save(&ctx, &account("alice", 1, 10)).is_err());
+    assert_eq!(by_balance.get_one(&ctx, &20).unwrap().1, account("alice", 1, 20));
+
+    // the key is free again once the row holding it changes
+    table.save(&ctx, &account("bob", 1, 11)).unwrap();
+    table.save(&ctx, &account("carol", 1, 10)).unwrap();
+    assert_eq!(by_balance.get_one(&ctx, &10).unwrap().1.owner, "carol");
+    let balances: Vec<_> = collect(by_balance.prefix_scan(&ctx, &[]).unwrap()).into_iter().map(|(b, _)| b).collect();
+    assert_eq!(balances, vec![10, 11, 20]);
+}
+
+// caps balances at 100, refuses to delete bob's accounts and hides empty accounts
+struct Rules;
+
+impl TableInterceptor<(String, u32), Account> for Rules {
+    fn on_read(&self, _ctx: &dyn StoreContext, value: Account) -> Result<Option<Account>, TableError> {
+        Ok(if value.balance == 0 { None } else { Some(value) })
+    }
+
+    fn before_save(&self, _ctx: &dyn StoreContext, _row_id: u64, value: &mut Account) -> Result<(), TableError> {
+        value.balance = value.balance.min(100);
+        Ok(())
+    }
+
+    fn before_delete(&self, _ctx: &dyn StoreContext, _row_id: u64, key: &(String, u32)) -> Result<(), TableError> {
+        if key.0 == "bob" {
+            return Err(TableError::Other(String::from("can't delete bob")));
+        }
+        Ok(())
+    }
+}
+
+// fails after the writes of saving a row with a balance of 13
+struct Unlucky;
+
+impl TableInterceptor<(String, u32), Account> for Unlucky {
+    fn after_save(&self, _ctx: &dyn StoreContext, _row_id: u64, value: &Account) -> Result<(), TableError> {
+        if value.balance == 13 {
+            return Err(TableError::Other(String::from("unlucky")));
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn test_interceptors() {
+    let mut store = MemStore::new();
+    let mut ctx = SimpleStoreContext::new();
+    ctx.mount(StoreKey::new("accounts"), &mut store).unwrap();
+    let mut table = accounts_table();
+    let by_balance = table.add_index("balance", Box::new(|a: &Account| a.balance));
+    table.add_interceptor(Box::new(Rules));
+    table.add_interceptor(Box::new(Unlucky));
+
+    table.save(&ctx, &account("bob", 1, 500)).unwrap();
+    assert_eq!(table.get_one(&ctx, &(String::from("bob"), 1)).unwrap().1.balance, 100);
+
+    table.save(&ctx, &account("alice", 1, 0)).unwrap();
+    assert!(matches!(table.get_one(&ctx, &(String::from("alice"), 1)), Err(TableError::NotFound)));
+    assert_eq!(collect(table.prefix_scan(&ctx, &[]).unwrap()).len(), 1);
+    assert!(collect(by_balance.get(&ctx, &0).unwrap()).is_empty());
+
+    assert!(table.delete(&ctx, &(String::from("bob"), 1)).is_err());
+    assert!(table.has(&ctx, &(String::from("bob"), 1)).unwrap());
+    table.delete(&ctx, &(String::from("alice"), 1)).unwrap();
+
+    // a failing after_save rolls back the row, its index entries and its row ID
+    assert!(table.save(&ctx, &account("carol", 1, 13)).is_err());
+    assert!(!table.has(&ctx, &(String::from("carol"), 1)).unwrap());
+    assert!(!by_balance.has(&ctx, &13).unwrap());
+    assert!(table.save(&ctx, &account("bob", 1, 13)).is_err());
+    assert_eq!(table.get_one(&ctx, &(String::from("bob"), 1)).unwrap().1.balance, 100);
+    assert!(by_balance.has(&ctx, &100).unwrap());
+    assert_eq!(table.save(&ctx, &account("carol", 1, 14)).unwrap(), 3);
+}
+
+#[test]
+fn test_pagination() {
+    let mut store = MemStore::new();
+    let mut ctx = SimpleStoreContext::new();
+    ctx.mount(StoreKey::new("accounts"), &mut store).unwrap();
+    let mut table = accounts_table();
+    let by_balance = table.add_index("balance", Box::new(|a: &Account| a.balance));
+    for number in 1..=5 {
+        table.save(&ctx, &account("alice", number, u64::from(number) * 10)).unwrap();
+    }
+    table.save(&ctx, &account("bob", 1, 30)).unwrap();
+
+    let alice = KeyBuilder::new().push(&String::from("alice")).build();
+    let mut req = PageRequest { limit: 2, cursor: None, count_total: true };
+    let mut numbers = Vec::new();
+    loop {
+        let page = table.prefix_scan_page(&ctx, &alice, &req).unwrap();
+        assert_eq!(page.total, Some(5));
+        assert!(page.items.len() <= 2);
+        numbers.extend(page.items.into_iter().map(|((_, n), _)| n));
+        match page.next_cursor {
+            None => break,
+            cursor => req.cursor = cursor,
+        }
+    }
+    assert_eq!(numbers, vec![1, 2, 3, 4, 5]);
+
+    let req = PageRequest { limit: 3, cursor: None, count_total: false };
+    let page = by_balance.reverse_prefix_scan_page(&ctx, &[], &req).unwrap();
+    let balances: Vec<_> = page.items.iter().map(|(b, _)| *b).collect();
+    assert_eq!(balances, vec![50, 40, 30]);
+    assert_eq!(page.total, None);
+    let req = PageRequest { cursor: page.next_cursor, ..req };
+    let page = by_balance.reverse_prefix_scan_page(&ctx, &[], &req).unwrap();
+    let owners: Vec<_> = page.items.iter().map(|(b, a)| (*b, a.owner.as_str())).collect();
+    assert_eq!(owners, vec![(30, "alice"), (20, "alice"), (10, "alice")]);
+    assert!(page.next_cursor.is_none());
+
+    // cursors only work for the scan they came from
+    let bob = KeyBuilder::new().push(&String::from("bob")).build();
+    assert!(table.prefix_scan_page(&ctx, &bob, &req).is_err());
+    assert!(table.prefix_scan_page(&ctx, &[], &PageRequest::default()).is_err());
+}
+
+// version 1 of the account schema, which stores balances in cents
+fn accounts_table_v1() -> TableImpl<(String, u32), Account> {
+    let mut table = accounts_table();
+    table.add_migration(Box::new(|bz| {
+        let mut account = AccountMarshaller.unmarshal(&bz)?;
+        account.balance *= 100;
+        AccountMarshaller.marshal(&account)
+    }));
+    table
+}
+
+#[test]
+fn test_lazy_migration() {
+    let mut store = MemStore::new();
+    let mut ctx = SimpleStoreContext::new();
+    ctx.mount(StoreKey::new("accounts"), &mut store).unwrap();
+    accounts_table().save(&ctx, &account("bob", 1, 10)).unwrap();
+    accounts_table().save(&ctx, &account("alice", 1, 20)).unwrap();
+
+    let table = accounts_table_v1();
+    let bob = (String::from("bob"), 1);
+    assert_eq!(table.get_one(&ctx, &bob).unwrap().1.balance, 1000);
+    assert_eq!(collect(table.prefix_scan(&ctx, &[]).unwrap()).len(), 2);
+    table.save(&ctx, &account("bob", 1, 1500)).unwrap();
+    assert_eq!(table.get_one(&ctx, &bob).unwrap().1.balance, 1500);
+    // the saved row is now at version 1, which the old schema can't read
+    assert!(accounts_table().get_one(&ctx, &bob).is_err());
+    assert_eq!(accounts_table().get_one(&ctx, &(String::from("alice"), 1)).unwrap().1.balance, 20);
+}
+
+#[test]
+fn test_eager_migration() {
+    let mut store = MemStore::new();
+    let mut ctx = SimpleStoreContext::new();
+    ctx.mount(StoreKey::new("accounts"), &mut store).unwrap();
+    let mut old = accounts_table();
+    old.add_index("balance", Box::new(|a: &Account| a.balance));
+    old.save(&ctx, &account("bob", 1, 10)).unwrap();
+    old.save(&ctx, &account("alice", 1, 20)).unwrap();
+
+    let mut table = accounts_table_v1();
+    let by_balance = table.add_index("balance", Box::new(|a: &Account| a.balance));
+    let by_number = table.add_unique_index("number", Box::new(|a: &Account| a.number));
+    // alice and bob have the same number
+    assert!(matches!(table.migrate(&ctx), Err(TableError::UniqueConstraint { .. })));
+    assert!(by_balance.has(&ctx, &10).unwrap());
+    assert_eq!(old.get_one(&ctx, &(String::from("bob"), 1)).unwrap().1.balance, 10);
+
+    old.delete(&ctx, &(String::from("alice"), 1)).unwrap();
+    assert_eq!(table.migrate(&ctx).unwrap(), 1);
+    assert!(!by_balance.has(&ctx, &10).unwrap());
+    assert_eq!(collect(by_balance.get(&ctx, &1000).unwrap()).len(), 1);
+    assert_eq!(by_number.get_one(&ctx, &1).unwrap().1.owner, "bob");
+    assert_eq!(table.migrate(&ctx).unwrap(), 0);
+}
+
+#[test]
+fn test_genesis() {
+    let mut store = MemStore::new();
+    let mut ctx = SimpleStoreContext::new();
+    ctx.mount(StoreKey::new("accounts"), &mut store).unwrap();
+    let table = accounts_table();
+    table.save(&ctx, &account("bob", 1, 10)).unwrap();
+    table.save(&ctx, &account("alice", 1, 20)).unwrap();
+    table.save(&ctx, &account("carol", 1, 30)).unwrap();
+    table.delete(&ctx, &(String::from("carol"), 1)).unwrap();
+
+    let genesis = table.export_json(&ctx, &AccountJson).unwrap();
+    assert_eq!(genesis.to_string(), concat!(
+        r#"{"rows":[{"row_id":2,"value":{"balance":20,"number":1,"owner":"alice"}},"#,
+        r#"{"row_id":1,"value":{"balance":10,"number":1,"owner":"bob"}}],"schema_version":0,"sequence":3}"#));
+    let mut lines = Vec::new();
+    table.export_json_lines(&ctx, &AccountJson, &mut lines).unwrap();
+    assert_eq!(String::from_utf8(lines.clone()).unwrap().lines().count(), 3);
+
+    let mut imported = MemStore::new();
+    let mut import_ctx = SimpleStoreContext::new();
+    import_ctx.mount(StoreKey::new("accounts"), &mut imported).unwrap();
+    let mut table = accounts_table();
+    table.add_unique_index("number", Box::new(|a: &Account| a.number));
+    // the unique index rejects the rows
+    assert!(matches!(table.import_json(&import_ctx, &AccountJson, &genesis), Err(TableError::UniqueConstraint { .. })));
+    assert!(import_ctx.kv_store(&StoreKey::new("accounts")).unwrap().iterator(None, None).unwrap().next().unwrap().is_none());
+
+    let mut table = accounts_table();
+    let by_balance = table.add_index("balance", Box::new(|a: &Account| a.balance));
+    table.import_json_lines(&import_ctx, &AccountJson, &mut lines.as_slice()).unwrap();
+    assert_eq!(table.export_json(&import_ctx, &AccountJson).unwrap(), genesis);
+    assert_eq!(table.row_id(&import_ctx, &(String::from("alice"), 1)).unwrap(), Some(2));
+    assert_eq!(collect(by_balance.get(&import_ctx, &10).unwrap())[0].1.owner, "bob");
+    assert_eq!(table.save(&import_ctx, &account("dave", 1, 0)).unwrap(), 4);
+    // only into empty tables
+    assert!(table.import_json(&import_ctx, &AccountJson, &genesis).is_err());
+
+    let mut store = MemStore::new();
+    let mut ctx = SimpleStoreContext::new();
+    ctx.mount(StoreKey::new("accounts"), &mut store).unwrap();
+    let table = accounts_table();
+    let invalid = [
+        json!({"rows": [], "schema_version": 1, "sequence": 0}),
+        json!({"rows": [{"row_id": 1, "value": {"owner": "bob"}}], "schema_version": 0, "sequence": 1}),
+        json!({"rows": [{"row_id": 2, "value": {"owner": "bob", "number": 1, "balance": 0}}], "schema_version": 0, "sequence": 1}),
+        json!({"rows": [{"row_id": 1, "value": {"owner": "bob", "number": 1, "balance": 0}},
+                        {"row_id": 2, "value": {"owner": "bob", "number": 1, "balance": 5}}], "schema_version": 0, "sequence": 2}),
+    ];
+    for genesis in invalid.iter() {
+        assert!(table.import_json(&ctx, &AccountJson, genesis).is_err());
+    }
+}
+
+#[test]
+fn test_change_events() {
+    let mut store = MemStore::new();
+    let events = EventManager::new();
+    let mut ctx = SimpleStoreContext::new();
+    ctx.mount(StoreKey::new("accounts"), &mut store).unwrap();
+    ctx.set_events(&events);
+    let mut table = accounts_table();
+    table.emit_changes("accounts", Some(Box::new(|old: &Account, new: &Account| {
+        if old.balance != new.balance { vec![String::from("balance")] } else { vec![] }
+    })));
+    table.add_interceptor(Box::new(Unlucky));
+
+    table.save(&ctx, &account("bob", 1, 10)).unwrap();
+    table.save(&ctx, &account("bob", 1, 20)).unwrap();
+    assert!(table.save(&ctx, &account("bob", 1, 13)).is_err());
+    table.delete(&ctx, &(String::from("bob"), 1)).unwrap();
+
+    let key: String = encode_key(&(String::from("bob"), 1u32)).iter().map(|b| format!("{:02x}", b)).collect();
+    let change = |op: &str| Event::new("table_change").indexed_attr("table", "accounts").attr("op", op).attr("primary_key", &key);
+    assert_eq!(events.take(), vec![
+        change(ChangeOp::Create.as_str()),
+        change(ChangeOp::Update.as_str()).attr("changed_fields", "balance"),
+        change(ChangeOp::Delete.as_str()),
+    ]);
+}
+
+#[test]
+fn test_batch_store() {
+    let mut store = MemStore::new();
+    {
+        let mut batch = store.new_batch();
+        let mut ctx = SimpleStoreContext::new();
+        ctx.mount(StoreKey::new("accounts"), &mut *batch).unwrap();
+        accounts_table().save(&ctx, &account("bob", 1, 10)).unwrap();
+    }
+    // the batch was dropped without writing
+    assert!(store.iterator(None, None).unwrap().next().unwrap().is_none());
+}
+
+#[test]
+fn test_unmounted_store() {
+    let ctx = SimpleStoreContext::new();
+    assert!(accounts_table().save(&ctx, &account("bob", 1, 10)).is_err());
+}
+
+struct OwnedStores(RefCell<MemStore>);
+
+impl StoreContext for OwnedStores {
+    fn kv_store(&self, _key: &StoreKey) -> Result<KVStoreRef<'_>, TableError> {
+        Ok(RefMut::map(self.0.borrow_mut(), |s| s as &mut KVStore))
+    }
+}
+
+#[test]
+fn test_store_context() {
+    let table = accounts_table();
+    let ctx = SimpleContext::new();
+    assert!(store_context(&ctx).is_err());
+    let stores: Rc<dyn StoreContext> = Rc::new(OwnedStores(RefCell::new(MemStore::new())));
+    let ctx = ctx.with(&STORE_CONTEXT, stores);
+    table.save(&store_context(&ctx).unwrap(), &Account { owner: String::from("alice"), number: 1, balance: 10 }).unwrap();
+    assert!(table.has(&store_context(&ctx).unwrap(), &(String::from("alice"), 1)).unwrap());
+}
+
+#[test]
+fn test_store_context_events() {
+    let mut table = accounts_table();
+    table.emit_changes("accounts", None);
+    let stores: Rc<dyn StoreContext> = Rc::new(OwnedStores(RefCell::new(MemStore::new())));
+    let ctx = SimpleContext::new().with(&STORE_CONTEXT, stores).with(&EVENTS, EventManager::new());
+    table.save(&store_context(&ctx).unwrap(), &account("alice", 1, 10)).unwrap();
+    // a failed sub-execution drops the change events of its saves, but not the saves, which
+    // need a store batch of their own to be discarded
+    let res: Result<(), TableError> = with_events(&ctx, |sub| {
+        table.save(&store_context(sub)?, &account("bob", 1, 10))?;
+        Err(TableError::NotFound)
+    });
+    assert!(res.is_err());
+    let events = ctx.get(&EVENTS).unwrap().take();
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0].event_type, "table_change");
+    assert!(table.has(&store_context(&ctx).unwrap(), &(String::from("bob"), 1)).unwrap());
+}
+
+// saves `value` like a transaction delivered by regen_abci, which fails after saving if `fail`,
+// returning the events it reports
+fn deliver(ctx: &SimpleContext, table: &TableImpl<(String, u32), Account>, value: &Account, fail: bool) -> Vec<Event> {
+    let (_, events) = collect_events(ctx, |ctx| {
+        table.save(&store_context(ctx)?, value)?;
+        if fail { Err(TableError::NotFound) } else { Ok(()) }
+    }, |res| res.is_ok());
+    events
+}
+
+#[test]
+fn test_deliver_table_changes() {
+    let mut table = accounts_table();
+    table.emit_changes("accounts", None);
+    let stores: Rc<dyn StoreContext> = Rc::new(OwnedStores(RefCell::new(MemStore::new())));
+    let ctx = SimpleContext::new().with(&STORE_CONTEXT, stores);
+    let key: String = encode_key(&(String::from("alice"), 1u32)).iter().map(|b| format!("{:02x}", b)).collect();
+    let events = deliver(&ctx, &table, &account("alice", 1, 10), false);
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0].event_type, "table_change");
+    assert_eq!(events[0].kv_pairs(), vec![
+        (b"table".to_vec(), b"accounts".to_vec()),
+        (b"op".to_vec(), b"create".to_vec()),
+        (b"primary_key".to_vec(), key.into_bytes()),
+    ]);
+    // the changes of a failed transaction aren't reported
+    assert!(deliver(&ctx, &table, &account("bob", 1, 10), true).is_empty());
+}
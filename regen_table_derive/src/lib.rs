@@ -0,0 +1,167 @@
+extern crate proc_macro;
+
+use proc_macro::TokenStream;
+use proc_macro2::Span;
+use quote::{format_ident, quote};
+use syn::{parse_macro_input, Data, DeriveInput, Error, Fields, Field, Ident};
+
+/// Derives `regen_table::TableRow`, see there for the attributes.
+#[proc_macro_derive(TableRow, attributes(primary_key, index, unique))]
+pub fn derive_table_row(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    match table_row(&input) {
+        Ok(tokens) => tokens.into(),
+        Err(e) => e.to_compile_error().into(),
+    }
+}
+
+enum Kind {
+    PrimaryKey,
+    Index,
+    Unique,
+}
+
+fn kind(field: &Field) -> Result<Option<Kind>, Error> {
+    let mut kind = None;
+    for attr in field.attrs.iter() {
+        let k = if attr.path.is_ident("primary_key") {
+            Kind::PrimaryKey
+        } else if attr.path.is_ident("index") {
+            Kind::Index
+        } else if attr.path.is_ident("unique") {
+            Kind::Unique
+        } else {
+            continue;
+        };
+        if !attr.tokens.is_empty() {
+            return Err(Error::new_spanned(attr, "table row attributes don't take arguments"));
+        }
+        if kind.is_some() {
+            return Err(Error::new_spanned(attr, "a field can only have one of #[primary_key], #[index] and #[unique]"));
+        }
+        kind = Some(k);
+    }
+    Ok(kind)
+}
+
+fn table_row(input: &DeriveInput) -> Result<proc_macro2::TokenStream, Error> {
+    let name = &input.ident;
+    let vis = &input.vis;
+    if !input.generics.params.is_empty() {
+        return Err(Error::new_spanned(&input.generics, "TableRow can't be derived for generic types"));
+    }
+    let fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => &fields.named,
+            _ => return Err(Error::new_spanned(name, "TableRow needs a struct with named fields")),
+        },
+        _ => return Err(Error::new_spanned(name, "TableRow needs a struct with named fields")),
+    };
+
+    let mut pk_names = Vec::new();
+    let mut pk_types = Vec::new();
+    let mut index_fields = Vec::new();
+    let mut index_types = Vec::new();
+    let mut index_adds = Vec::new();
+    let mut encoders = Vec::new();
+    for field in fields.iter() {
+        let kind = match kind(field)? {
+            None => continue,
+            Some(kind) => kind,
+        };
+        let ident = field.ident.as_ref().unwrap();
+        let ty = &field.ty;
+        let index_name = ident.to_string();
+        match kind {
+            Kind::PrimaryKey => {
+                pk_names.push(ident);
+                pk_types.push(ty);
+                continue;
+            }
+            Kind::Index => {
+                index_types.push(quote!(::regen_table::SecondaryIndex<#ty, #name>));
+                index_adds.push(quote!(table.add_index(#index_name, Box::new(|v: &#name| v.#ident.clone()))));
+            }
+            Kind::Unique => {
+                index_types.push(quote!(::regen_table::UniqueSecondaryIndex<#ty, #name>));
+                index_adds.push(quote!(table.add_unique_index(#index_name, Box::new(|v: &#name| v.#ident.clone()))));
+            }
+        }
+        // the encoder of the index would clash with the one of the primary key
+        if ident == "primary" {
+            return Err(Error::new_spanned(ident, "an indexed field can't be named `primary`"));
+        }
+        let encoder = format_ident!("encode_{}_key", ident);
+        encoders.push(quote! {
+            /// The order-preserving encoding of an index key, as stored in the index.
+            #vis fn #encoder(key: &#ty) -> Vec<u8> {
+                ::regen_table::key::encode_key(key)
+            }
+        });
+        index_fields.push(ident);
+    }
+
+    let (pk_type, pk_value) = match pk_names.len() {
+        0 => return Err(Error::new(Span::call_site(), "TableRow needs a #[primary_key] field")),
+        1 => {
+            let (ty, ident) = (pk_types[0], pk_names[0]);
+            (quote!(#ty), quote!(self.#ident.clone()))
+        }
+        _ => (quote!((#(#pk_types,)*)), quote!((#(self.#pk_names.clone(),)*))),
+    };
+    let indexes = Ident::new(&format!("{}Indexes", name), name.span());
+
+    Ok(quote! {
+        /// The indexes of the table created by `TableRow::table`.
+        #vis struct #indexes {
+            #(pub #index_fields: #index_types,)*
+        }
+
+        impl #name {
+            /// The order-preserving encoding of a primary key, as stored in the table.
+            #vis fn encode_primary_key(key: &#pk_type) -> Vec<u8> {
+                ::regen_table::key::encode_key(key)
+            }
+
+            #(#encoders)*
+        }
+
+        impl ::regen_table::TableRow for #name {
+            type PrimaryKey = #pk_type;
+            type Indexes = #indexes;
+
+            fn primary_key(&self) -> #pk_type {
+                #pk_value
+            }
+
+            fn table(store_key: ::regen_table::StoreKey, marshaller: Box<dyn ::regen_table::Marshaller<Self>>) -> (::regen_table::TableImpl<#pk_type, Self>, #indexes) {
+                #[allow(unused_mut)]
+                let mut table = ::regen_table::TableImpl::new(store_key, marshaller, Box::new(|v: &#name| ::regen_table::TableRow::primary_key(v)));
+                let indexes = #indexes {
+                    #(#index_fields: #index_adds,)*
+                };
+                (table, indexes)
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use syn::parse_quote;
+    use super::table_row;
+
+    #[test]
+    fn test_index_named_primary() {
+        let input = parse_quote! {
+            struct Row {
+                #[primary_key]
+                id: u64,
+                #[index]
+                primary: String,
+            }
+        };
+        let err = table_row(&input).unwrap_err();
+        assert_eq!(err.to_string(), "an indexed field can't be named `primary`");
+    }
+}